@@ -0,0 +1,238 @@
+use crate::console::{Halt, Instr, Vm};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+
+/// Reads and assembles a program file.
+pub fn load(path: &str) -> Result<Vec<Instr>, Box<dyn Error>> {
+    assemble(&fs::read_to_string(path)?)
+}
+
+/// Assembles a program, where lines are either `label:` or an instruction whose operand is
+/// a signed offset or a label. Everything after a `;` is a comment.
+pub fn assemble(source: &str) -> Result<Vec<Instr>, Box<dyn Error>> {
+    let mut labels = HashMap::new();
+    let mut lines = vec![];
+    for (n, line) in source.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(label) = line.strip_suffix(':') {
+            if labels.insert(label, lines.len()).is_some() {
+                return Err(format!("line {}: duplicate label {}", n + 1, label).into());
+            }
+        } else {
+            lines.push((n + 1, line));
+        }
+    }
+
+    lines
+        .iter()
+        .enumerate()
+        .map(|(ip, &(n, line))| {
+            let (op, arg) = line.split_at(line.find(' ').unwrap_or(line.len()));
+            let arg = arg.trim();
+            let resolved = match labels.get(arg) {
+                Some(&target) => format!("{} {:+}", op, target as isize - ip as isize),
+                None => format!("{} {}", op, arg),
+            };
+            resolved
+                .parse()
+                .map_err(|e| format!("line {}: {}: {:?}", n, e, line).into())
+        })
+        .collect()
+}
+
+/// Disassembles a program, naming jump targets `l<address>` (or `end` when it terminates)
+/// and marking the instructions of the infinite loop, if it has one.
+pub fn disassemble(program: &[Instr]) -> String {
+    let labels = labels(program);
+    let in_loop = find_loop(program).into_iter().collect::<HashSet<_>>();
+
+    let mut out = String::new();
+    for (ip, instr) in program.iter().enumerate() {
+        if let Some(label) = labels.get(&(ip as isize)) {
+            let _ = writeln!(out, "{}:", label);
+        }
+        let label = labels.get(&instr_target(instr, ip));
+        let line = match (instr, label) {
+            (Instr::Nop(_), Some(label)) => format!("nop {}", label),
+            (Instr::Jmp(_), Some(label)) => format!("jmp {}", label),
+            _ => instr.to_string(),
+        };
+        if in_loop.contains(&ip) {
+            let _ = writeln!(out, "    {:<12}; loop", line);
+        } else {
+            let _ = writeln!(out, "    {}", line);
+        }
+    }
+    if let Some(label) = labels.get(&(program.len() as isize)) {
+        let _ = writeln!(out, "{}:", label);
+    }
+    out
+}
+
+/// Exports the control-flow graph in Graphviz DOT format. Solid edges are the flow of the
+/// program as is, dashed ones the flow once a `nop` or `jmp` is flipped, and the infinite
+/// loop is drawn in red.
+pub fn to_dot(program: &[Instr]) -> String {
+    let in_loop = find_loop(program).into_iter().collect::<HashSet<_>>();
+    let node = |target: isize| {
+        if target == program.len() as isize {
+            "end".to_string()
+        } else if target < 0 || target > program.len() as isize {
+            format!("out{}", target).replace('-', "_")
+        } else {
+            format!("i{}", target)
+        }
+    };
+
+    let mut out = String::from("digraph program {\n    node [shape=box, fontname=monospace];\n");
+    let mut outside = BTreeMap::new();
+    for (ip, instr) in program.iter().enumerate() {
+        let (colour, edge) = if in_loop.contains(&ip) {
+            (", color=red", " [color=red]")
+        } else {
+            ("", "")
+        };
+        let _ = writeln!(out, "    i{} [label=\"{}: {}\"{}];", ip, ip, instr, colour);
+
+        let next = instr.successor(ip);
+        let _ = writeln!(out, "    i{} -> {}{};", ip, node(next), edge);
+        outside.insert(node(next), next);
+        if let Some(flipped) = instr.flipped() {
+            let alt = flipped.successor(ip);
+            let _ = writeln!(out, "    i{} -> {} [style=dashed];", ip, node(alt));
+            outside.insert(node(alt), alt);
+        }
+    }
+    for (name, target) in outside {
+        if !name.starts_with('i') {
+            let _ = writeln!(out, "    {} [label=\"{}\", shape=oval];", name, target);
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Returns the addresses of the instructions that repeat forever, in execution order.
+pub fn find_loop(program: &[Instr]) -> Vec<usize> {
+    let mut vm = Vm::new(program);
+    if vm.run() != Halt::Loop {
+        return vec![];
+    }
+
+    let start = vm.registers().ip;
+    let mut ip = start;
+    let mut addresses = vec![];
+    loop {
+        addresses.push(ip);
+        ip = program[ip].successor(ip) as usize;
+        if ip == start {
+            return addresses;
+        }
+    }
+}
+
+fn instr_target(instr: &Instr, ip: usize) -> isize {
+    match instr {
+        Instr::Nop(offset) | Instr::Jmp(offset) => ip as isize + offset,
+        Instr::Acc(_) => ip as isize + 1,
+    }
+}
+
+fn labels(program: &[Instr]) -> HashMap<isize, String> {
+    program
+        .iter()
+        .enumerate()
+        .filter(|(_, instr)| !matches!(instr, Instr::Acc(_)))
+        .map(|(ip, instr)| instr_target(instr, ip))
+        .filter(|&target| target >= 0 && target <= program.len() as isize)
+        .map(|target| {
+            let label = if target == program.len() as isize {
+                "end".to_string()
+            } else {
+                format!("l{}", target)
+            };
+            (target, label)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::SAMPLE;
+
+    #[test]
+    fn test_round_trip() {
+        let program = assemble(SAMPLE).unwrap();
+        let text = program.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+        assert_eq!(text.join("\n"), SAMPLE);
+
+        let listing = disassemble(&program);
+        assert_eq!(assemble(&listing).unwrap(), program);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let program = assemble(SAMPLE).unwrap();
+        let expected = "\
+l0:
+    nop l0
+l1:
+    acc +1      ; loop
+    jmp l6      ; loop
+l3:
+    acc +3      ; loop
+    jmp l1      ; loop
+    acc -99
+l6:
+    acc +1      ; loop
+    jmp l3      ; loop
+    acc +6
+";
+        assert_eq!(disassemble(&program), expected);
+        assert_eq!(find_loop(&program), vec![1, 2, 6, 7, 3, 4]);
+    }
+
+    #[test]
+    fn test_assemble() {
+        let program = assemble("start:\n  acc +1 ; one\n  jmp done\n  jmp start\ndone:\n").unwrap();
+        assert_eq!(program, vec![Instr::Acc(1), Instr::Jmp(2), Instr::Jmp(-2)]);
+        assert!(find_loop(&program).is_empty());
+
+        let err = assemble("acc +1\njmp nowhere").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: No match: \"jmp nowhere\"");
+        assert!(assemble("a:\na:\n").is_err());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let program = assemble("nop +0\nacc +2000000000\nacc +2000000000").unwrap();
+        assert!(find_loop(&program).is_empty());
+        assert!(!disassemble(&program).contains("; loop"));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let program = assemble("nop +2\njmp -1\nacc +1").unwrap();
+        let expected = "\
+digraph program {
+    node [shape=box, fontname=monospace];
+    i0 [label=\"0: nop +2\", color=red];
+    i0 -> i1 [color=red];
+    i0 -> i2 [style=dashed];
+    i1 [label=\"1: jmp -1\", color=red];
+    i1 -> i0 [color=red];
+    i1 -> i2 [style=dashed];
+    i2 [label=\"2: acc +1\"];
+    i2 -> end;
+    end [label=\"3\", shape=oval];
+}
+";
+        assert_eq!(to_dot(&program), expected);
+    }
+}
@@ -0,0 +1,516 @@
+use crate::console::trace::{Trace, Tracer};
+use crate::console::{asm, Halt, Instr, Vm};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+/// Interactive debugger for handheld console programs, reading commands from stdin.
+pub fn run(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut program = asm::load(path)?;
+    println!(
+        "Loaded {} instructions from {}, type `help` for commands",
+        program.len(),
+        path
+    );
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut settings = Settings::default();
+    loop {
+        let mut debugger = Debugger::with_settings(&program, settings);
+        let command = loop {
+            print!("(dbg) ");
+            io::stdout().flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return Ok(()),
+            };
+            match debugger.execute(&line) {
+                Command::Output(s) => print!("{}", s),
+                Command::Quit => return Ok(()),
+                command => break command,
+            }
+        };
+
+        // breakpoints and watches survive restarts, to follow the same spots in a patched program
+        settings = debugger.settings;
+        if let Command::Flip(addr) = command {
+            match program.get(addr).and_then(Instr::flipped) {
+                Some(instr) => {
+                    println!("Flipped {}: {}, restarting", addr, instr);
+                    program[addr] = instr;
+                }
+                None => println!("Can't flip instruction {}", addr),
+            }
+        }
+    }
+}
+
+/// What the REPL should do after a command.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Command {
+    Output(String),
+    Flip(usize),
+    Restart,
+    Quit,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Breakpoint {
+    Address(usize),
+    Acc(Comparison, i32),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    const ALL: [Comparison; 6] = [
+        Comparison::Eq,
+        Comparison::Ne,
+        Comparison::Lt,
+        Comparison::Le,
+        Comparison::Gt,
+        Comparison::Ge,
+    ];
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|op| op.symbol() == s)
+    }
+
+    fn holds(self, a: i32, b: i32) -> bool {
+        match self {
+            Comparison::Eq => a == b,
+            Comparison::Ne => a != b,
+            Comparison::Lt => a < b,
+            Comparison::Le => a <= b,
+            Comparison::Gt => a > b,
+            Comparison::Ge => a >= b,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Watches {
+    acc: bool,
+    ip: bool,
+}
+
+/// What the user set up, kept when the program restarts.
+#[derive(Default)]
+struct Settings {
+    breakpoints: Vec<Breakpoint>,
+    watches: Watches,
+}
+
+pub struct Debugger<'a> {
+    tracer: Tracer<'a>,
+    settings: Settings,
+}
+
+const HELP: &str = "\
+step [n]            execute n instructions (default 1)
+continue            run until a breakpoint or the program halts
+break <addr>        stop before executing the instruction at addr
+break acc <op> <n>  stop when acc compares to n, op is one of == != < <= > >=
+delete <n>          remove breakpoint number n
+breakpoints         list breakpoints
+watch acc|ip        print acc whenever it changes, or ip whenever a jmp moves it
+unwatch acc|ip      stop watching the register
+regs                show registers
+list [addr]         show instructions around addr (default ip)
+trace [n]           show the last n executed instructions (default 10)
+loop                show the instructions of the detected infinite loop
+replay <file> <n>   restart and run n steps, checking them against a JSONL trace
+flip <addr>         swap a nop and a jmp, then restart
+restart             start over
+quit
+";
+
+impl<'a> Debugger<'a> {
+    #[allow(dead_code)]
+    pub fn new(program: &'a [Instr]) -> Self {
+        Self::with_settings(program, Settings::default())
+    }
+
+    fn with_settings(program: &'a [Instr], settings: Settings) -> Self {
+        Self {
+            tracer: Tracer::new(program),
+            settings,
+        }
+    }
+
+    fn vm(&self) -> &Vm<'a, Instr> {
+        self.tracer.vm()
+    }
+
+    pub fn execute(&mut self, line: &str) -> Command {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let output = match words.as_slice() {
+            [] => String::new(),
+            ["help"] | ["h"] => HELP.to_string(),
+            ["step"] | ["s"] => self.step(1),
+            ["step", n] | ["s", n] => match n.parse() {
+                Ok(n) => self.step(n),
+                Err(_) => format!("Invalid count: {}\n", n),
+            },
+            ["continue"] | ["c"] => self.cont(),
+            ["break", "acc", op, n] | ["b", "acc", op, n] => {
+                match (Comparison::parse(op), n.parse()) {
+                    (Some(op), Ok(n)) => self.add_breakpoint(Breakpoint::Acc(op, n)),
+                    _ => format!("Invalid condition: acc {} {}\n", op, n),
+                }
+            }
+            ["break", addr] | ["b", addr] => match addr.parse() {
+                Ok(addr) => self.add_breakpoint(Breakpoint::Address(addr)),
+                Err(_) => format!("Invalid address: {}\n", addr),
+            },
+            ["delete", n] | ["d", n] => match n.parse::<usize>() {
+                Ok(n) if n > 0 && n <= self.settings.breakpoints.len() => {
+                    self.settings.breakpoints.remove(n - 1);
+                    format!("Deleted breakpoint {}\n", n)
+                }
+                _ => format!("No breakpoint {}\n", n),
+            },
+            ["breakpoints"] => self.list_breakpoints(),
+            ["watch", reg] | ["w", reg] => self.set_watch(reg, true),
+            ["unwatch", reg] => self.set_watch(reg, false),
+            ["regs"] | ["r"] => self.regs(),
+            ["list"] | ["l"] => self.list(self.vm().registers().ip),
+            ["list", addr] | ["l", addr] => match addr.parse() {
+                Ok(addr) => self.list(addr),
+                Err(_) => format!("Invalid address: {}\n", addr),
+            },
+            ["trace"] | ["t"] => self.show_trace(10),
+            ["trace", n] | ["t", n] => match n.parse() {
+                Ok(n) => self.show_trace(n),
+                Err(_) => format!("Invalid count: {}\n", n),
+            },
+            ["loop"] => self.show_loop(),
+            ["flip", addr] => match addr.parse() {
+                Ok(addr) => return Command::Flip(addr),
+                Err(_) => format!("Invalid address: {}\n", addr),
+            },
+            ["replay", path, n] => match n.parse() {
+                Ok(n) => self.replay(path, n),
+                Err(_) => format!("Invalid count: {}\n", n),
+            },
+            ["restart"] => return Command::Restart,
+            ["quit"] | ["q"] => return Command::Quit,
+            _ => format!("Unknown command: {}\n", line.trim()),
+        };
+        Command::Output(output)
+    }
+
+    fn step(&mut self, n: usize) -> String {
+        let mut out = String::new();
+        for _ in 0..n {
+            if self.single_step(&mut out) {
+                break;
+            }
+        }
+        out
+    }
+
+    fn cont(&mut self) -> String {
+        let mut out = String::new();
+        loop {
+            if self.single_step(&mut out) {
+                return out;
+            }
+            if let Some(i) = self.hit_breakpoint() {
+                let _ = writeln!(out, "Breakpoint {}: {}", i + 1, self.describe_regs());
+                return out;
+            }
+        }
+    }
+
+    /// Executes one instruction, recording it and reporting watched registers. Returns
+    /// whether the VM is halted.
+    fn single_step(&mut self, out: &mut String) -> bool {
+        if let Some(halt) = self.vm().halted() {
+            let _ = writeln!(out, "{}", describe_halt(halt));
+            return true;
+        }
+
+        let before = self.vm().registers();
+        let halt = self.tracer.step();
+        let after = self.vm().registers();
+        let instr = &self.vm().program()[before.ip];
+
+        let _ = writeln!(out, "{:>5}: {}", before.ip, instr);
+        if self.settings.watches.acc && before.acc != after.acc {
+            let _ = writeln!(out, "       acc: {} -> {}", before.acc, after.acc);
+        }
+        if self.settings.watches.ip && !matches!(instr, Instr::Nop(_) | Instr::Acc(_)) {
+            let _ = writeln!(out, "       ip: {} -> {}", before.ip, after.ip);
+        }
+        if let Some(halt) = halt {
+            let _ = writeln!(out, "{}", describe_halt(halt));
+            return true;
+        }
+        false
+    }
+
+    fn hit_breakpoint(&self) -> Option<usize> {
+        let regs = self.vm().registers();
+        self.settings.breakpoints.iter().position(|b| match *b {
+            Breakpoint::Address(addr) => regs.ip == addr,
+            Breakpoint::Acc(op, n) => op.holds(regs.acc, n),
+        })
+    }
+
+    fn add_breakpoint(&mut self, b: Breakpoint) -> String {
+        self.settings.breakpoints.push(b);
+        format!(
+            "Breakpoint {}: {}\n",
+            self.settings.breakpoints.len(),
+            describe_breakpoint(&b)
+        )
+    }
+
+    fn list_breakpoints(&self) -> String {
+        if self.settings.breakpoints.is_empty() {
+            return "No breakpoints\n".into();
+        }
+        let mut out = String::new();
+        for (i, b) in self.settings.breakpoints.iter().enumerate() {
+            let _ = writeln!(out, "{}: {}", i + 1, describe_breakpoint(b));
+        }
+        out
+    }
+
+    fn set_watch(&mut self, reg: &str, on: bool) -> String {
+        match reg {
+            "acc" => self.settings.watches.acc = on,
+            "ip" => self.settings.watches.ip = on,
+            _ => return format!("Unknown register: {}\n", reg),
+        }
+        format!(
+            "{} {}\n",
+            if on { "Watching" } else { "Stopped watching" },
+            reg
+        )
+    }
+
+    fn regs(&self) -> String {
+        let mut out = format!("{}\n", self.describe_regs());
+        if let Some(halt) = self.vm().halted() {
+            let _ = writeln!(out, "{}", describe_halt(halt));
+        }
+        out
+    }
+
+    fn describe_regs(&self) -> String {
+        let regs = self.vm().registers();
+        format!("ip={} acc={}", regs.ip, regs.acc)
+    }
+
+    /// Shows instructions around `addr`: `>` marks the current instruction, `*` breakpoints
+    /// and `.` instructions already executed.
+    fn list(&self, addr: usize) -> String {
+        let program = self.vm().program();
+        let ip = self.vm().registers().ip;
+        let mut out = String::new();
+        let start = addr.saturating_sub(5);
+        let end = addr.saturating_add(6).min(program.len());
+        for (i, instr) in program.iter().enumerate().take(end).skip(start) {
+            let current = if i == ip { '>' } else { ' ' };
+            let bp = if self.settings.breakpoints.contains(&Breakpoint::Address(i)) {
+                '*'
+            } else {
+                ' '
+            };
+            let visited = if self.vm().visited()[i] { '.' } else { ' ' };
+            let _ = writeln!(out, "{}{}{} {:>5}: {}", current, bp, visited, i, instr);
+        }
+        out
+    }
+
+    fn show_trace(&self, n: usize) -> String {
+        let mut out = String::new();
+        let steps = &self.tracer.trace().steps;
+        for (i, e) in steps.iter().enumerate().skip(steps.len().saturating_sub(n)) {
+            let _ = writeln!(
+                out,
+                "#{:<5} {:>5}: {:<12} acc={}",
+                i,
+                e.ip,
+                e.instr.to_string(),
+                e.acc
+            );
+        }
+        out
+    }
+
+    fn replay(&mut self, path: &str, n: usize) -> String {
+        let trace = match File::open(path) {
+            Ok(f) => Trace::read_jsonl(BufReader::new(f)),
+            Err(e) => Err(e.into()),
+        };
+        match trace.and_then(|t| t.replay(self.vm().program(), n)) {
+            Ok(tracer) => {
+                self.tracer = tracer;
+                format!("Replayed {} steps: {}\n", n, self.describe_regs())
+            }
+            Err(e) => format!("Replay failed: {}\n", e),
+        }
+    }
+
+    /// Shows the instructions executed since the VM last passed the address it would run
+    /// again, which is the loop caught by the VM's record of executed instructions.
+    fn show_loop(&self) -> String {
+        if self.vm().halted() != Some(Halt::Loop) {
+            return "No loop detected yet\n".into();
+        }
+        let ip = self.vm().registers().ip;
+        let steps = &self.tracer.trace().steps;
+        let start = steps.iter().rposition(|e| e.ip == ip).unwrap_or(0);
+        let mut out = format!(
+            "Loop of {} instructions back to {}:\n",
+            steps.len() - start,
+            ip
+        );
+        for e in &steps[start..] {
+            let _ = writeln!(out, "{:>5}: {}", e.ip, e.instr);
+        }
+        out
+    }
+}
+
+fn describe_halt(halt: Halt) -> String {
+    match halt {
+        Halt::Terminated => "Program terminated".into(),
+        Halt::Loop => "Loop detected: next instruction was already executed".into(),
+        Halt::OutOfBounds(target) => format!("Jump out of bounds to {}", target),
+        Halt::Overflow => "Accumulator overflow".into(),
+    }
+}
+
+fn describe_breakpoint(b: &Breakpoint) -> String {
+    match b {
+        Breakpoint::Address(addr) => format!("address {}", addr),
+        Breakpoint::Acc(op, n) => format!("acc {} {}", op.symbol(), n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::{parse_program, SAMPLE};
+
+    fn output(c: Command) -> String {
+        match c {
+            Command::Output(s) => s,
+            c => panic!("unexpected command {:?}", c),
+        }
+    }
+
+    #[test]
+    fn test_step_and_watch() {
+        let p = parse_program(SAMPLE);
+        let mut d = Debugger::new(&p);
+        output(d.execute("watch acc"));
+        let out = output(d.execute("step 2"));
+        assert_eq!(out, "    0: nop +0\n    1: acc +1\n       acc: 0 -> 1\n");
+        assert_eq!(output(d.execute("regs")), "ip=2 acc=1\n");
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let p = parse_program(SAMPLE);
+        let mut d = Debugger::new(&p);
+        assert_eq!(
+            output(d.execute("break acc >= 2")),
+            "Breakpoint 1: acc >= 2\n"
+        );
+        assert_eq!(output(d.execute("breakpoints")), "1: acc >= 2\n");
+        let out = output(d.execute("continue"));
+        assert!(out.ends_with("Breakpoint 1: ip=7 acc=2\n"));
+
+        output(d.execute("delete 1"));
+        output(d.execute("break 4"));
+        let out = output(d.execute("c"));
+        assert!(out.ends_with("Breakpoint 1: ip=4 acc=5\n"));
+    }
+
+    #[test]
+    fn test_restart_keeps_settings() {
+        let p = parse_program(SAMPLE);
+        let mut d = Debugger::new(&p);
+        output(d.execute("break 7"));
+        output(d.execute("watch acc"));
+
+        let mut patched = p.clone();
+        patched[7] = Instr::Nop(-4);
+        let mut d = Debugger::with_settings(&patched, d.settings);
+        let out = output(d.execute("continue"));
+        assert!(out.contains("       acc: 0 -> 1\n"));
+        assert!(out.ends_with("Breakpoint 1: ip=7 acc=2\n"));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let p = vec![
+            Instr::Nop(0),
+            Instr::Acc(2000000000),
+            Instr::Acc(2000000000),
+        ];
+        let mut d = Debugger::new(&p);
+        let out = output(d.execute("step 3"));
+        assert!(out.ends_with("    2: acc +2000000000\nAccumulator overflow\n"));
+        assert_eq!(
+            output(d.execute("regs")),
+            "ip=2 acc=2000000000\nAccumulator overflow\n"
+        );
+    }
+
+    #[test]
+    fn test_loop() {
+        let p = parse_program(SAMPLE);
+        let mut d = Debugger::new(&p);
+        assert_eq!(output(d.execute("loop")), "No loop detected yet\n");
+        let out = output(d.execute("continue"));
+        assert!(out.ends_with("Loop detected: next instruction was already executed\n"));
+
+        let out = output(d.execute("loop"));
+        assert!(out.starts_with("Loop of 6 instructions back to 1:\n"));
+        assert_eq!(
+            output(d.execute("trace 1")),
+            "#6         4: jmp -3       acc=5\n"
+        );
+    }
+
+    #[test]
+    fn test_commands() {
+        let p = parse_program(SAMPLE);
+        let mut d = Debugger::new(&p);
+        assert_eq!(d.execute("flip 7"), Command::Flip(7));
+        assert_eq!(d.execute("restart"), Command::Restart);
+        assert_eq!(d.execute("quit"), Command::Quit);
+        assert_eq!(
+            output(d.execute("frobnicate")),
+            "Unknown command: frobnicate\n"
+        );
+        assert!(output(d.execute("list")).starts_with(">       0: nop +0\n"));
+        assert_eq!(output(d.execute("list 18446744073709551615")), "");
+    }
+}
@@ -0,0 +1,293 @@
+pub mod asm;
+pub mod debugger;
+pub mod trace;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Registers {
+    pub ip: usize,
+    pub acc: i32,
+}
+
+/// Where execution continues after an instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Flow {
+    Next,
+    Jump(isize),
+}
+
+/// An instruction set the VM can run. Instructions that can't execute return the reason the
+/// VM halts instead.
+pub trait Instruction {
+    fn execute(&self, regs: &mut Registers) -> Result<Flow, Halt>;
+}
+
+/// Why the VM stopped.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Halt {
+    /// The instruction right after the last one was reached.
+    Terminated,
+    /// The next instruction was already executed once, so the program would run forever.
+    Loop,
+    /// A jump targeted an address outside of the program.
+    OutOfBounds(isize),
+    /// The accumulator overflowed, `ip` is left on the instruction that caused it.
+    Overflow,
+}
+
+pub struct Vm<'a, I> {
+    program: &'a [I],
+    regs: Registers,
+    visited: Vec<bool>,
+    halted: Option<Halt>,
+}
+
+#[allow(dead_code)]
+impl<'a, I> Vm<'a, I>
+where
+    I: Instruction,
+{
+    pub fn new(program: &'a [I]) -> Self {
+        let mut vm = Self {
+            program,
+            regs: Registers::default(),
+            visited: vec![false; program.len()],
+            halted: None,
+        };
+        vm.halted = vm.check(0);
+        vm
+    }
+
+    pub fn program(&self) -> &'a [I] {
+        self.program
+    }
+
+    pub fn registers(&self) -> Registers {
+        self.regs
+    }
+
+    /// Tells which instructions were executed so far.
+    pub fn visited(&self) -> &[bool] {
+        &self.visited
+    }
+
+    pub fn halted(&self) -> Option<Halt> {
+        self.halted
+    }
+
+    /// Executes the instruction at `ip`, then returns the reason the VM stopped, if it did.
+    /// Does nothing once halted.
+    pub fn step(&mut self) -> Option<Halt> {
+        if self.halted.is_some() {
+            return self.halted;
+        }
+
+        let ip = self.regs.ip;
+        self.visited[ip] = true;
+        let target = match self.program[ip].execute(&mut self.regs) {
+            Ok(Flow::Next) => ip as isize + 1,
+            Ok(Flow::Jump(offset)) => ip as isize + offset,
+            Err(halt) => {
+                self.halted = Some(halt);
+                return self.halted;
+            }
+        };
+        self.halted = self.check(target);
+        if !matches!(self.halted, Some(Halt::OutOfBounds(_))) {
+            self.regs.ip = target as usize;
+        }
+
+        self.halted
+    }
+
+    pub fn run(&mut self) -> Halt {
+        loop {
+            if let Some(halt) = self.step() {
+                return halt;
+            }
+        }
+    }
+
+    fn check(&self, target: isize) -> Option<Halt> {
+        if target < 0 || target as usize > self.program.len() {
+            Some(Halt::OutOfBounds(target))
+        } else if target as usize == self.program.len() {
+            Some(Halt::Terminated)
+        } else if self.visited[target as usize] {
+            Some(Halt::Loop)
+        } else {
+            None
+        }
+    }
+}
+
+/// The handheld console's instruction set.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Instr {
+    Nop(isize),
+    Jmp(isize),
+    Acc(i32),
+}
+
+impl Instr {
+    /// Swaps a `nop` for a `jmp` and vice versa, the fix for corrupted programs.
+    pub fn flipped(&self) -> Option<Instr> {
+        match *self {
+            Instr::Nop(offset) => Some(Instr::Jmp(offset)),
+            Instr::Jmp(offset) => Some(Instr::Nop(offset)),
+            Instr::Acc(_) => None,
+        }
+    }
+
+    /// Returns the address of the next instruction when this one sits at `ip`.
+    pub fn successor(&self, ip: usize) -> isize {
+        match self {
+            Instr::Jmp(offset) => ip as isize + offset,
+            _ => ip as isize + 1,
+        }
+    }
+}
+
+impl Instruction for Instr {
+    fn execute(&self, regs: &mut Registers) -> Result<Flow, Halt> {
+        match self {
+            Instr::Nop(_) => Ok(Flow::Next),
+            Instr::Jmp(offset) => Ok(Flow::Jump(*offset)),
+            Instr::Acc(value) => {
+                regs.acc = regs.acc.checked_add(*value).ok_or(Halt::Overflow)?;
+                Ok(Flow::Next)
+            }
+        }
+    }
+}
+
+impl FromStr for Instr {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"^(jmp|acc|nop) ([-+]\d+)$").unwrap();
+        }
+        let caps = RE.captures(s).ok_or("No match")?;
+
+        match &caps[1] {
+            "nop" => Ok(Self::Nop(caps[2].parse()?)),
+            "acc" => Ok(Self::Acc(caps[2].parse()?)),
+            "jmp" => Ok(Self::Jmp(caps[2].parse()?)),
+            _ => Err("Unknown instruction".into()),
+        }
+    }
+}
+
+impl Display for Instr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Instr::Nop(offset) => write!(f, "nop {:+}", offset),
+            Instr::Jmp(offset) => write!(f, "jmp {:+}", offset),
+            Instr::Acc(value) => write!(f, "acc {:+}", value),
+        }
+    }
+}
+
+/// The example program from the puzzle, which loops.
+#[cfg(test)]
+pub(crate) const SAMPLE: &str =
+    "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6";
+
+/// Parses a program, one instruction per line.
+#[cfg(test)]
+pub(crate) fn parse_program(s: &str) -> Vec<Instr> {
+    s.lines().map(|l| l.parse().unwrap()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terminates() {
+        let p = parse_program("nop +0\nacc +1\njmp +2\nacc +5\nacc -3");
+        let mut vm = Vm::new(&p);
+        assert_eq!(vm.run(), Halt::Terminated);
+        assert_eq!(vm.registers(), Registers { ip: 5, acc: -2 });
+        assert_eq!(vm.visited(), &[true, true, true, false, true]);
+    }
+
+    #[test]
+    fn test_loop() {
+        let p = parse_program(SAMPLE);
+        let mut vm = Vm::new(&p);
+        assert_eq!(vm.run(), Halt::Loop);
+        assert_eq!(vm.registers(), Registers { ip: 1, acc: 5 });
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let p = parse_program("acc +1\njmp -2");
+        let mut vm = Vm::new(&p);
+        assert_eq!(vm.run(), Halt::OutOfBounds(-1));
+        assert_eq!(vm.registers(), Registers { ip: 1, acc: 1 });
+
+        let p = parse_program("jmp +3\nnop +0");
+        assert_eq!(Vm::new(&p).run(), Halt::OutOfBounds(3));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let p = parse_program("nop +0\nacc +2000000000\nacc +2000000000");
+        let mut vm = Vm::new(&p);
+        assert_eq!(vm.run(), Halt::Overflow);
+        assert_eq!(
+            vm.registers(),
+            Registers {
+                ip: 2,
+                acc: 2000000000
+            }
+        );
+        assert_eq!(vm.step(), Some(Halt::Overflow));
+    }
+
+    #[test]
+    fn test_step() {
+        let p = parse_program("acc +2\njmp -1");
+        let mut vm = Vm::new(&p);
+        assert_eq!(vm.step(), None);
+        assert_eq!(vm.registers(), Registers { ip: 1, acc: 2 });
+        assert_eq!(vm.step(), Some(Halt::Loop));
+        assert_eq!(vm.step(), Some(Halt::Loop));
+        assert_eq!(vm.registers(), Registers { ip: 0, acc: 2 });
+
+        let empty: Vec<Instr> = vec![];
+        assert_eq!(Vm::new(&empty).halted(), Some(Halt::Terminated));
+    }
+
+    #[test]
+    fn test_custom_instruction_set() {
+        enum Double {
+            Acc,
+            Skip,
+        }
+
+        impl Instruction for Double {
+            fn execute(&self, regs: &mut Registers) -> Result<Flow, Halt> {
+                match self {
+                    Double::Acc => {
+                        regs.acc = regs.acc * 2 + 1;
+                        Ok(Flow::Next)
+                    }
+                    Double::Skip => Ok(Flow::Jump(2)),
+                }
+            }
+        }
+
+        let p = vec![Double::Acc, Double::Skip, Double::Acc, Double::Acc];
+        let mut vm = Vm::new(&p);
+        assert_eq!(vm.run(), Halt::Terminated);
+        assert_eq!(vm.registers().acc, 3);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::console::{Halt, Instr, Vm};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+
+/// An executed instruction and the accumulator after it ran.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Step {
+    pub ip: usize,
+    pub instr: Instr,
+    pub acc: i32,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+}
+
+impl Trace {
+    /// Writes one JSON object per step.
+    pub fn write_jsonl<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for s in &self.steps {
+            writeln!(
+                w,
+                r#"{{"ip":{},"instr":"{}","acc":{}}}"#,
+                s.ip, s.instr, s.acc
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn read_jsonl<R: BufRead>(r: R) -> Result<Self, Box<dyn Error>> {
+        lazy_static! {
+            static ref RE: Regex =
+                Regex::new(r#"^\{"ip":(\d+),"instr":"([^"]*)","acc":(-?\d+)\}$"#).unwrap();
+        }
+
+        let mut steps = vec![];
+        for (n, line) in r.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let caps = RE
+                .captures(line.trim())
+                .ok_or_else(|| format!("line {}: not a trace step: {:?}", n + 1, line))?;
+            steps.push(Step {
+                ip: caps[1].parse()?,
+                instr: caps[2].parse()?,
+                acc: caps[3].parse()?,
+            });
+        }
+        Ok(Self { steps })
+    }
+
+    /// Runs `program` for `n` steps, checking that it does what the trace recorded, and
+    /// returns a tracer in the state it had at that point.
+    pub fn replay<'a>(&self, program: &'a [Instr], n: usize) -> Result<Tracer<'a>, Box<dyn Error>> {
+        if n > self.steps.len() {
+            return Err(format!("trace only has {} steps", self.steps.len()).into());
+        }
+
+        let mut tracer = Tracer::new(program);
+        for (i, expected) in self.steps[..n].iter().enumerate() {
+            if tracer.vm().halted().is_some() {
+                return Err(format!("step {}: program halted", i).into());
+            }
+            tracer.step();
+            let actual = &tracer.trace().steps[i];
+            if actual != expected {
+                return Err(format!(
+                    "step {}: expected {} at {} (acc={}), found {} at {} (acc={})",
+                    i,
+                    expected.instr,
+                    expected.ip,
+                    expected.acc,
+                    actual.instr,
+                    actual.ip,
+                    actual.acc
+                )
+                .into());
+            }
+        }
+        Ok(tracer)
+    }
+
+    /// Returns the index of the first step where the two traces differ, or `None` when they
+    /// are the same.
+    pub fn divergence(&self, other: &Trace) -> Option<usize> {
+        self.steps
+            .iter()
+            .zip(&other.steps)
+            .position(|(a, b)| a != b)
+            .or_else(|| {
+                if self.steps.len() == other.steps.len() {
+                    None
+                } else {
+                    Some(self.steps.len().min(other.steps.len()))
+                }
+            })
+    }
+}
+
+/// Runs a program on the VM, recording every executed instruction.
+pub struct Tracer<'a> {
+    vm: Vm<'a, Instr>,
+    trace: Trace,
+}
+
+impl<'a> Tracer<'a> {
+    pub fn new(program: &'a [Instr]) -> Self {
+        Self {
+            vm: Vm::new(program),
+            trace: Trace::default(),
+        }
+    }
+
+    pub fn vm(&self) -> &Vm<'a, Instr> {
+        &self.vm
+    }
+
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    pub fn into_trace(self) -> Trace {
+        self.trace
+    }
+
+    pub fn step(&mut self) -> Option<Halt> {
+        if self.vm.halted().is_some() {
+            return self.vm.halted();
+        }
+
+        let ip = self.vm.registers().ip;
+        let halt = self.vm.step();
+        self.trace.steps.push(Step {
+            ip,
+            instr: self.vm.program()[ip].clone(),
+            acc: self.vm.registers().acc,
+        });
+        halt
+    }
+
+    pub fn run(&mut self) -> Halt {
+        loop {
+            if let Some(halt) = self.step() {
+                return halt;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::{parse_program, Registers};
+
+    #[test]
+    fn test_record() {
+        let p = parse_program("acc +3\njmp +2\nacc -99\nacc -1");
+        let mut tracer = Tracer::new(&p);
+        assert_eq!(tracer.run(), Halt::Terminated);
+        let ips = tracer.trace().steps.iter().map(|s| (s.ip, s.acc));
+        assert_eq!(ips.collect::<Vec<_>>(), vec![(0, 3), (1, 3), (3, 2)]);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let p = parse_program("acc +2000000000\nacc +2000000000\nacc +1");
+        let mut tracer = Tracer::new(&p);
+        assert_eq!(tracer.run(), Halt::Overflow);
+        let last = tracer.trace().steps.last().unwrap();
+        assert_eq!((last.ip, last.acc), (1, 2000000000));
+    }
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        let p = parse_program("nop +0\nacc -7\njmp -2");
+        let mut tracer = Tracer::new(&p);
+        assert_eq!(tracer.run(), Halt::Loop);
+
+        let mut out = vec![];
+        tracer.trace().write_jsonl(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(
+            text.lines().nth(1),
+            Some(r#"{"ip":1,"instr":"acc -7","acc":-7}"#)
+        );
+        assert_eq!(&Trace::read_jsonl(text.as_bytes()).unwrap(), tracer.trace());
+        assert!(Trace::read_jsonl(&b"{\"ip\":0}"[..]).is_err());
+    }
+
+    #[test]
+    fn test_replay_and_diff() {
+        let original = parse_program("acc +1\njmp -1\nacc +2");
+        let mut patched = original.clone();
+        patched[1] = Instr::Nop(-1);
+
+        let mut tracer = Tracer::new(&original);
+        tracer.run();
+        let a = tracer.into_trace();
+        let mut tracer = Tracer::new(&patched);
+        tracer.run();
+        let b = tracer.into_trace();
+
+        let tracer = a.replay(&original, 1).unwrap();
+        assert_eq!(tracer.vm().registers(), Registers { ip: 1, acc: 1 });
+        assert!(a.replay(&original, 3).is_err());
+        assert!(b.replay(&original, 2).is_err());
+
+        assert_eq!(a.divergence(&a), None);
+        assert_eq!(a.divergence(&b), Some(1));
+        let prefix = Trace {
+            steps: b.steps[..2].to_vec(),
+        };
+        assert_eq!(b.divergence(&prefix), Some(2));
+    }
+}
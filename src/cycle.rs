@@ -0,0 +1,212 @@
+use std::{collections::HashMap, hash::Hash};
+
+/// Where the sequence `x0, f(x0), f(f(x0)), ...` starts repeating. The detection functions
+/// never return if the sequence doesn't cycle, which can't happen with a finite state space.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cycle<S> {
+    /// Number of steps before the first state that belongs to the cycle.
+    pub start: usize,
+    pub len: usize,
+    /// State after `start` steps.
+    pub state: S,
+}
+
+#[allow(dead_code)]
+impl<S> Cycle<S>
+where
+    S: Clone,
+{
+    /// Returns the state after `n` steps from `x0`, only stepping through at most one period
+    /// of the cycle.
+    pub fn nth(&self, x0: &S, f: impl FnMut(&S) -> S, n: usize) -> S {
+        if n < self.start {
+            iterate(x0.clone(), f, n)
+        } else {
+            iterate(self.state.clone(), f, (n - self.start) % self.len)
+        }
+    }
+}
+
+/// Floyd's tortoise and hare, using constant memory.
+#[allow(dead_code)]
+pub fn floyd<S>(x0: S, mut f: impl FnMut(&S) -> S) -> Cycle<S>
+where
+    S: Clone + Eq,
+{
+    let mut tortoise = f(&x0);
+    let mut hare = f(&tortoise);
+    while tortoise != hare {
+        tortoise = f(&tortoise);
+        let next = f(&hare);
+        hare = f(&next);
+    }
+
+    let mut start = 0;
+    tortoise = x0;
+    while tortoise != hare {
+        tortoise = f(&tortoise);
+        hare = f(&hare);
+        start += 1;
+    }
+
+    let mut len = 1;
+    hare = f(&tortoise);
+    while tortoise != hare {
+        hare = f(&hare);
+        len += 1;
+    }
+
+    Cycle {
+        start,
+        len,
+        state: tortoise,
+    }
+}
+
+/// Brent's algorithm, using constant memory and fewer steps than Floyd's.
+#[allow(dead_code)]
+pub fn brent<S>(x0: S, mut f: impl FnMut(&S) -> S) -> Cycle<S>
+where
+    S: Clone + Eq,
+{
+    let mut power = 1;
+    let mut len = 1;
+    let mut tortoise = x0.clone();
+    let mut hare = f(&x0);
+    while tortoise != hare {
+        if power == len {
+            tortoise = hare.clone();
+            power *= 2;
+            len = 0;
+        }
+        hare = f(&hare);
+        len += 1;
+    }
+
+    let mut start = 0;
+    tortoise = x0.clone();
+    hare = iterate(x0, &mut f, len);
+    while tortoise != hare {
+        tortoise = f(&tortoise);
+        hare = f(&hare);
+        start += 1;
+    }
+
+    Cycle {
+        start,
+        len,
+        state: tortoise,
+    }
+}
+
+/// Remembers every state seen, so that `f` is called exactly once per step until the first
+/// repeat. Trades memory for speed when `f` is expensive.
+#[allow(dead_code)]
+pub fn find<S>(x0: S, mut f: impl FnMut(&S) -> S) -> Cycle<S>
+where
+    S: Clone + Eq + Hash,
+{
+    let mut seen = HashMap::new();
+    let mut state = x0;
+    let mut i = 0;
+    loop {
+        if let Some(&start) = seen.get(&state) {
+            return Cycle {
+                start,
+                len: i - start,
+                state: state_at(&seen, start),
+            };
+        }
+        let next = f(&state);
+        seen.insert(state, i);
+        state = next;
+        i += 1;
+    }
+}
+
+/// Returns the state after `n` steps from `x0`, extrapolating through the cycle when `n` is
+/// past its start.
+#[allow(dead_code)]
+pub fn nth_state<S>(x0: S, mut f: impl FnMut(&S) -> S, n: usize) -> S
+where
+    S: Clone + Eq,
+{
+    brent(x0.clone(), &mut f).nth(&x0, f, n)
+}
+
+fn iterate<S>(x0: S, mut f: impl FnMut(&S) -> S, n: usize) -> S {
+    (0..n).fold(x0, |x, _| f(&x))
+}
+
+fn state_at<S: Clone>(seen: &HashMap<S, usize>, i: usize) -> S {
+    seen.iter()
+        .find(|&(_, &j)| j == i)
+        .map(|(s, _)| s.clone())
+        .expect("state was recorded")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0 -> 1 -> 2 -> 3 -> 4 -> 5 -> 6 -> 3 -> ...
+    fn step(x: &u32) -> u32 {
+        if *x == 6 {
+            3
+        } else {
+            x + 1
+        }
+    }
+
+    #[test]
+    fn test_algorithms_agree() {
+        let expected = Cycle {
+            start: 3,
+            len: 4,
+            state: 3,
+        };
+        assert_eq!(floyd(0, step), expected);
+        assert_eq!(brent(0, step), expected);
+        assert_eq!(find(0, step), expected);
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        let f = |x: &u64| (x / 2).max(1);
+        let expected = Cycle {
+            start: 6,
+            len: 1,
+            state: 1,
+        };
+        assert_eq!(floyd(100, f), expected);
+        assert_eq!(brent(100, f), expected);
+        assert_eq!(find(100, f), expected);
+    }
+
+    #[test]
+    fn test_stateful_step() {
+        let mut calls = 0;
+        let mut counted = |x: &u32| {
+            calls += 1;
+            step(x)
+        };
+        assert_eq!(floyd(0, &mut counted).len, 4);
+        assert_eq!(brent(0, &mut counted).len, 4);
+        assert_eq!(find(0, &mut counted).len, 4);
+        assert_eq!(nth_state(0, &mut counted, 10), 6);
+        assert!(calls > 0);
+    }
+
+    #[test]
+    fn test_nth_state() {
+        let f = |x: &u64| (x * x + 1) % 1009;
+        for n in 0..100 {
+            assert_eq!(nth_state(2, f, n), iterate(2, f, n));
+        }
+
+        let cycle = find(2, f);
+        let n = 1_000_000_000_000;
+        assert_eq!(cycle.nth(&2, f, n), nth_state(2, f, n));
+        assert_eq!(nth_state(2, f, n), nth_state(2, f, n - cycle.len * 1000));
+    }
+}
@@ -0,0 +1,419 @@
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::iter::Peekable;
+
+/// How operators of the same precedence group.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+/// Why an operator couldn't compute a result.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OpError {
+    /// The result doesn't fit in an `i64`.
+    Overflow,
+    /// There's no result for these operands, like for a division by zero.
+    Undefined,
+}
+
+pub type OpResult = Result<i64, OpError>;
+
+pub struct BinaryOp {
+    pub symbol: &'static str,
+    pub precedence: u8,
+    pub assoc: Assoc,
+    pub apply: fn(i64, i64) -> OpResult,
+}
+
+pub struct PrefixOp {
+    pub symbol: &'static str,
+    pub precedence: u8,
+    pub apply: fn(i64) -> OpResult,
+}
+
+pub fn add(a: i64, b: i64) -> OpResult {
+    a.checked_add(b).ok_or(OpError::Overflow)
+}
+
+pub fn sub(a: i64, b: i64) -> OpResult {
+    a.checked_sub(b).ok_or(OpError::Overflow)
+}
+
+pub fn mul(a: i64, b: i64) -> OpResult {
+    a.checked_mul(b).ok_or(OpError::Overflow)
+}
+
+pub fn div(a: i64, b: i64) -> OpResult {
+    if b == 0 {
+        return Err(OpError::Undefined);
+    }
+    a.checked_div(b).ok_or(OpError::Overflow)
+}
+
+/// Integer exponentiation, undefined for negative exponents.
+pub fn pow(a: i64, b: i64) -> OpResult {
+    if b < 0 {
+        return Err(OpError::Undefined);
+    }
+    let b = b.try_into().map_err(|_| OpError::Overflow)?;
+    a.checked_pow(b).ok_or(OpError::Overflow)
+}
+
+pub fn neg(a: i64) -> OpResult {
+    a.checked_neg().ok_or(OpError::Overflow)
+}
+
+/// The operators of a "math" and their precedence (higher binds tighter), used to parse
+/// expressions of integers and parentheses by precedence climbing.
+#[derive(Default)]
+pub struct OperatorTable {
+    binary: Vec<BinaryOp>,
+    prefix: Vec<PrefixOp>,
+}
+
+/// A parsed expression. Displays fully parenthesized.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    Prefix(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Prefix(symbol, e) => write!(f, "({}{})", symbol, e),
+            Expr::Binary(symbol, lhs, rhs) => write!(f, "({} {} {})", lhs, symbol, rhs),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ExprError {
+    /// Parsing failed at `column`, counted from 1.
+    Parse { column: usize, message: String },
+    /// An operator's result is out of range.
+    Overflow(&'static str),
+    /// An operator has no result for its operands, like a division by zero.
+    Undefined(&'static str),
+    /// The expression uses an operator the table doesn't have.
+    UnknownOperator(&'static str),
+}
+
+impl ExprError {
+    fn from_op(error: OpError, symbol: &'static str) -> Self {
+        match error {
+            OpError::Overflow => ExprError::Overflow(symbol),
+            OpError::Undefined => ExprError::Undefined(symbol),
+        }
+    }
+}
+
+impl Display for ExprError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprError::Parse { column, message } => write!(f, "column {}: {}", column, message),
+            ExprError::Overflow(symbol) => write!(f, "overflow in {:?}", symbol),
+            ExprError::Undefined(symbol) => write!(f, "{:?} is undefined for its operands", symbol),
+            ExprError::UnknownOperator(symbol) => write!(f, "unknown operator {:?}", symbol),
+        }
+    }
+}
+
+impl Error for ExprError {}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Token<'a> {
+    Number(&'a str),
+    Op(&'a str),
+    Open,
+    Close,
+}
+
+/// Tokens along with their column.
+type Tokens<'a> = Peekable<std::vec::IntoIter<(usize, Token<'a>)>>;
+
+fn parse_error<T>(column: usize, message: impl Into<String>) -> Result<T, ExprError> {
+    Err(ExprError::Parse {
+        column,
+        message: message.into(),
+    })
+}
+
+#[allow(dead_code)]
+impl OperatorTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The usual rules: `+ -` below `* /` below unary minus below `^`, which is right
+    /// associative.
+    pub fn standard() -> Self {
+        Self::new()
+            .binary("+", 1, Assoc::Left, add)
+            .binary("-", 1, Assoc::Left, sub)
+            .binary("*", 2, Assoc::Left, mul)
+            .binary("/", 2, Assoc::Left, div)
+            .prefix("-", 3, neg)
+            .binary("^", 4, Assoc::Right, pow)
+    }
+
+    pub fn binary(
+        mut self,
+        symbol: &'static str,
+        precedence: u8,
+        assoc: Assoc,
+        apply: fn(i64, i64) -> OpResult,
+    ) -> Self {
+        self.binary.push(BinaryOp {
+            symbol,
+            precedence,
+            assoc,
+            apply,
+        });
+        self
+    }
+
+    pub fn prefix(
+        mut self,
+        symbol: &'static str,
+        precedence: u8,
+        apply: fn(i64) -> OpResult,
+    ) -> Self {
+        self.prefix.push(PrefixOp {
+            symbol,
+            precedence,
+            apply,
+        });
+        self
+    }
+
+    pub fn eval(&self, s: &str) -> Result<i64, ExprError> {
+        self.evaluate(&self.parse(s)?)
+    }
+
+    pub fn parse(&self, s: &str) -> Result<Expr, ExprError> {
+        let end = s.chars().count() + 1;
+        let mut tokens = self.tokenize(s)?.into_iter().peekable();
+        let expr = self.parse_expr(&mut tokens, 0, end)?;
+        match tokens.next() {
+            None => Ok(expr),
+            Some((column, Token::Close)) => parse_error(column, "unmatched ')'"),
+            Some((column, _)) => parse_error(column, "expected an operator"),
+        }
+    }
+
+    /// Evaluates an expression parsed with this table.
+    pub fn evaluate(&self, expr: &Expr) -> Result<i64, ExprError> {
+        match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::Prefix(symbol, e) => {
+                let op = self
+                    .prefix
+                    .iter()
+                    .find(|op| op.symbol == *symbol)
+                    .ok_or(ExprError::UnknownOperator(symbol))?;
+                let value = self.evaluate(e)?;
+                (op.apply)(value).map_err(|e| ExprError::from_op(e, symbol))
+            }
+            Expr::Binary(symbol, lhs, rhs) => {
+                let op = self
+                    .binary
+                    .iter()
+                    .find(|op| op.symbol == *symbol)
+                    .ok_or(ExprError::UnknownOperator(symbol))?;
+                let (lhs, rhs) = (self.evaluate(lhs)?, self.evaluate(rhs)?);
+                (op.apply)(lhs, rhs).map_err(|e| ExprError::from_op(e, symbol))
+            }
+        }
+    }
+
+    fn parse_expr(
+        &self,
+        tokens: &mut Tokens,
+        min_precedence: u8,
+        end: usize,
+    ) -> Result<Expr, ExprError> {
+        let mut lhs = match tokens.next() {
+            None => return parse_error(end, "unexpected end of expression"),
+            Some((column, Token::Number(n))) => match n.parse() {
+                Ok(n) => Expr::Number(n),
+                Err(_) => return parse_error(column, "number too large"),
+            },
+            Some((column, Token::Open)) => {
+                let e = self.parse_expr(tokens, 0, end)?;
+                match tokens.next() {
+                    Some((_, Token::Close)) => e,
+                    Some((c, _)) => return parse_error(c, "expected ')'"),
+                    None => return parse_error(end, format!("unclosed '(' at column {}", column)),
+                }
+            }
+            Some((column, Token::Op(symbol))) => {
+                let op = match self.prefix.iter().find(|op| op.symbol == symbol) {
+                    Some(op) => op,
+                    None => {
+                        return parse_error(column, format!("{:?} isn't a prefix operator", symbol))
+                    }
+                };
+                let e = self.parse_expr(tokens, op.precedence, end)?;
+                Expr::Prefix(op.symbol, Box::new(e))
+            }
+            Some((column, Token::Close)) => return parse_error(column, "unexpected ')'"),
+        };
+
+        while let Some(&(column, Token::Op(symbol))) = tokens.peek() {
+            let op = match self.binary.iter().find(|op| op.symbol == symbol) {
+                Some(op) => op,
+                None => {
+                    return parse_error(column, format!("{:?} isn't a binary operator", symbol))
+                }
+            };
+            if op.precedence < min_precedence {
+                break;
+            }
+            tokens.next();
+            let next_precedence = match op.assoc {
+                Assoc::Left => op.precedence + 1,
+                Assoc::Right => op.precedence,
+            };
+            let rhs = self.parse_expr(tokens, next_precedence, end)?;
+            lhs = Expr::Binary(op.symbol, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    /// Splits `s` into numbers, parentheses and the longest known operator symbols.
+    fn tokenize<'a>(&self, s: &'a str) -> Result<Vec<(usize, Token<'a>)>, ExprError> {
+        let symbols = self
+            .binary
+            .iter()
+            .map(|op| op.symbol)
+            .chain(self.prefix.iter().map(|op| op.symbol));
+
+        let mut tokens = vec![];
+        let mut rest = s.trim_start();
+        while !rest.is_empty() {
+            let column = s[..s.len() - rest.len()].chars().count() + 1;
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let (token, len) = if digits > 0 {
+                (Token::Number(&rest[..digits]), digits)
+            } else if rest.starts_with('(') {
+                (Token::Open, 1)
+            } else if rest.starts_with(')') {
+                (Token::Close, 1)
+            } else {
+                match symbols
+                    .clone()
+                    .filter(|symbol| rest.starts_with(symbol))
+                    .max_by_key(|symbol| symbol.len())
+                {
+                    Some(symbol) => (Token::Op(&rest[..symbol.len()]), symbol.len()),
+                    None => {
+                        let c = rest.chars().next().unwrap_or_default();
+                        return parse_error(column, format!("unexpected {:?}", c));
+                    }
+                }
+            };
+            tokens.push((column, token));
+            rest = rest[len..].trim_start();
+        }
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(r: Result<i64, ExprError>) -> usize {
+        match r {
+            Err(ExprError::Parse { column, .. }) => column,
+            r => panic!("expected a parse error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_standard() {
+        let t = OperatorTable::standard();
+        assert_eq!(t.eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(t.eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(t.eval("10 - 4 - 3"), Ok(3));
+        assert_eq!(t.eval("7 / 2 * 2"), Ok(6));
+        assert_eq!(t.eval("2 ^ 3 ^ 2"), Ok(512));
+        assert_eq!(t.eval("-2 ^ 2"), Ok(-4));
+        assert_eq!(t.eval("3 - -2"), Ok(5));
+        assert_eq!(t.eval("-(1 + 2) * 2"), Ok(-6));
+    }
+
+    #[test]
+    fn test_pretty_print() {
+        let t = OperatorTable::standard();
+        let e = t.parse("1 + 2 * 3 - -4 ^ 2").unwrap();
+        assert_eq!(e.to_string(), "((1 + (2 * 3)) - (-(4 ^ 2)))");
+        assert_eq!(t.parse(&e.to_string()), Ok(e));
+        assert_eq!(t.parse("((7))").unwrap().to_string(), "7");
+    }
+
+    #[test]
+    fn test_errors() {
+        let t = OperatorTable::standard();
+        assert_eq!(column(t.eval("")), 1);
+        assert_eq!(column(t.eval("1 +")), 4);
+        assert_eq!(column(t.eval("(1 + 2")), 7);
+        assert_eq!(column(t.eval("1 + 2)")), 6);
+        assert_eq!(column(t.eval("1 2")), 3);
+        assert_eq!(column(t.eval("1 % 2")), 3);
+        assert_eq!(column(t.eval("* 2")), 1);
+        assert_eq!(column(t.eval("(1 + 2 3)")), 8);
+        assert_eq!(
+            t.eval("1 + 2)").unwrap_err().to_string(),
+            "column 6: unmatched ')'"
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let t = OperatorTable::standard();
+        assert_eq!(
+            t.eval("2 ^ 62 + 2 ^ 62 - 1 + 2 ^ 62"),
+            Err(ExprError::Overflow("+"))
+        );
+        assert_eq!(t.eval("2 ^ 64"), Err(ExprError::Overflow("^")));
+        assert_eq!(t.eval("2 ^ 9999999999"), Err(ExprError::Overflow("^")));
+        assert_eq!(
+            t.eval("-(0 - 9223372036854775807 - 1)"),
+            Err(ExprError::Overflow("-"))
+        );
+        assert_eq!(column(t.eval("99999999999999999999")), 1);
+    }
+
+    #[test]
+    fn test_undefined() {
+        let t = OperatorTable::standard();
+        assert_eq!(t.eval("1 / (1 - 1)"), Err(ExprError::Undefined("/")));
+        assert_eq!(t.eval("2 ^ -1"), Err(ExprError::Undefined("^")));
+        assert_eq!(
+            t.eval("1 / 0").unwrap_err().to_string(),
+            "\"/\" is undefined for its operands"
+        );
+
+        let e = t.parse("2 ^ 3").unwrap();
+        let no_pow = OperatorTable::new().binary("*", 1, Assoc::Left, mul);
+        assert_eq!(no_pow.evaluate(&e), Err(ExprError::UnknownOperator("^")));
+    }
+
+    #[test]
+    fn test_custom_symbols() {
+        let t = OperatorTable::new()
+            .binary("*", 1, Assoc::Left, mul)
+            .binary("**", 2, Assoc::Right, pow);
+        assert_eq!(t.eval("2 * 2 ** 3"), Ok(16));
+    }
+}
@@ -1,17 +1,179 @@
 use std::{
-    collections::HashMap,
-    convert::TryFrom,
-    fmt::{Display, Error, Formatter},
-    io::{BufRead, BufReader, Read},
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, VecDeque},
+    convert::{TryFrom, TryInto},
+    fmt::{Debug, Display, Error, Formatter},
+    io::{self, BufRead, BufReader, Read, Write},
     iter::{repeat, FromIterator},
+    ops::{BitAnd, BitOr, BitXor},
     str::FromStr,
 };
 
-#[derive(Clone, Debug)]
+/// Declares an enum of grid cells from a table of variants and the characters they're written
+/// with, and implements `TryFrom<u8>`, `ToByte`, `Display` and `Default` for it. The first
+/// variant is the default.
+///
+/// ```ignore
+/// grid_cell! {
+///     #[derive(Clone, Debug, Eq, PartialEq)]
+///     pub enum Seat {
+///         Floor = '.',
+///         Empty = 'L',
+///         Occupied = '#',
+///     }
+/// }
+/// ```
+macro_rules! grid_cell {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $first:ident = $first_c:literal
+            $(, $variant:ident = $c:literal)* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $first,
+            $($variant,)*
+        }
+
+        impl ::std::convert::TryFrom<u8> for $name {
+            type Error = String;
+
+            fn try_from(value: u8) -> Result<Self, Self::Error> {
+                match value as char {
+                    $first_c => Ok(Self::$first),
+                    $($c => Ok(Self::$variant),)*
+                    _ => Err(concat!("expected one of ", $first_c $(, ", ", $c)*).into()),
+                }
+            }
+        }
+
+        impl $crate::grid::ToByte for $name {
+            fn to_byte(&self) -> u8 {
+                let c = match self {
+                    Self::$first => $first_c,
+                    $(Self::$variant => $c,)*
+                };
+                c as u8
+            }
+        }
+
+        impl ::std::default::Default for $name {
+            fn default() -> Self {
+                Self::$first
+            }
+        }
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                let c = match self {
+                    Self::$first => $first_c,
+                    $(Self::$variant => $c,)*
+                };
+                write!(f, "{}", c)
+            }
+        }
+    };
+}
+
+pub(crate) use grid_cell;
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "RawGrid<T>")
+)]
 pub struct Grid<T> {
     cells: Vec<T>,
     pub w: usize,
     pub h: usize,
+    wrap: Wrap,
+}
+
+/// Unchecked form of a deserialized grid, validated when converting it to a `Grid`.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RawGrid<T> {
+    cells: Vec<T>,
+    w: usize,
+    h: usize,
+    wrap: Wrap,
+}
+
+#[cfg(feature = "serde")]
+impl<T> TryFrom<RawGrid<T>> for Grid<T> {
+    type Error = String;
+
+    fn try_from(raw: RawGrid<T>) -> Result<Self, Self::Error> {
+        if raw.cells.len() != raw.w * raw.h {
+            return Err(format!(
+                "expected {} cells for a {}x{} grid, found {}",
+                raw.w * raw.h,
+                raw.w,
+                raw.h,
+                raw.cells.len()
+            ));
+        }
+        Ok(Self {
+            cells: raw.cells,
+            w: raw.w,
+            h: raw.h,
+            wrap: raw.wrap,
+        })
+    }
+}
+
+/// Cells that can be written as a single byte, the one they're parsed from with `TryFrom<u8>`.
+pub trait ToByte {
+    fn to_byte(&self) -> u8;
+}
+
+impl ToByte for u8 {
+    fn to_byte(&self) -> u8 {
+        *self
+    }
+}
+
+/// Controls which axes of a grid wrap around, making it a cylinder or a torus.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Wrap {
+    None,
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+impl Wrap {
+    fn horizontal(self) -> bool {
+        matches!(self, Wrap::Horizontal | Wrap::Both)
+    }
+
+    fn vertical(self) -> bool {
+        matches!(self, Wrap::Vertical | Wrap::Both)
+    }
+
+    fn swapped(self) -> Self {
+        match self {
+            Wrap::Horizontal => Wrap::Vertical,
+            Wrap::Vertical => Wrap::Horizontal,
+            w => w,
+        }
+    }
+
+    const ALL: [Wrap; 4] = [Wrap::None, Wrap::Horizontal, Wrap::Vertical, Wrap::Both];
+
+    fn name(self) -> &'static str {
+        match self {
+            Wrap::None => "none",
+            Wrap::Horizontal => "horizontal",
+            Wrap::Vertical => "vertical",
+            Wrap::Both => "both",
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -28,74 +190,200 @@ where
             cells: Vec::from_iter(repeat(val).take(w * h)),
             w,
             h,
+            wrap: Wrap::None,
         }
     }
 
-    pub fn from_reader<R: Read>(r: R) -> Result<Self, T::Error> {
-        let cells = BufReader::new(r)
+    pub fn from_reader<R: Read>(r: R) -> Result<Self, ParseGridError<T::Error>> {
+        let lines = BufReader::new(r)
             .lines()
-            .filter_map(|l| l.ok())
-            .map(|l| l.bytes().map(T::try_from).collect::<Result<Vec<_>, _>>())
-            .collect::<Result<Vec<_>, _>>()?;
-        let h = cells.len();
-        let w = cells.first().map_or(0, |c| c.len());
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ParseGridError::Io)?;
+        parse_lines(lines.iter().map(String::as_str))
+    }
+}
 
-        Ok(Self {
-            cells: cells.into_iter().flatten().collect(),
+#[allow(dead_code)]
+impl<T> Grid<T>
+where
+    T: Clone,
+{
+    pub fn from_fn(w: usize, h: usize, mut f: impl FnMut(usize, usize) -> T) -> Self {
+        let mut cells = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                cells.push(f(x, y));
+            }
+        }
+        Self {
+            cells,
             w,
             h,
-        })
+            wrap: Wrap::None,
+        }
     }
 
-    pub fn from_map(points: HashMap<Point, T>) -> Self {
-        let mut min_x = 0;
-        let mut max_x = 0;
-        let mut min_y = 0;
-        let mut max_y = 0;
-        for pt in points.keys() {
-            if pt.x < min_x {
-                min_x = pt.x;
-            }
-            if pt.x > max_x {
-                max_x = pt.x;
-            }
-            if pt.y < min_y {
-                min_y = pt.y;
-            }
-            if pt.y > max_y {
-                max_y = pt.y;
-            }
-        }
+    /// Builds a grid just large enough to hold every point of a sparse map, filling the gaps
+    /// with `fill`. Also returns the map coordinates of the grid's top left cell.
+    pub fn from_map(points: HashMap<Point, T>, fill: T) -> (Self, Point) {
+        let min_x = points.keys().map(|pt| pt.x).min().unwrap_or(0);
+        let max_x = points.keys().map(|pt| pt.x).max().unwrap_or(-1);
+        let min_y = points.keys().map(|pt| pt.y).min().unwrap_or(0);
+        let max_y = points.keys().map(|pt| pt.y).max().unwrap_or(-1);
         let w = (max_x - min_x + 1) as usize;
         let h = (max_y - min_y + 1) as usize;
-        let x_offset = -min_x;
-        let y_offset = -min_y;
+        let origin = Point::new(min_x, min_y);
 
-        let mut grid = Self::new(w as usize, h as usize);
+        let mut grid = Self {
+            cells: vec![fill; w * h],
+            w,
+            h,
+            wrap: Wrap::None,
+        };
 
         for (pt, cell) in points {
-            let x = (pt.x + x_offset) as usize;
-            let y = (pt.y + y_offset) as usize;
+            let x = (pt.x - origin.x) as usize;
+            let y = (pt.y - origin.y) as usize;
             grid.set((x, y), cell);
         }
 
-        grid
+        (grid, origin)
+    }
+
+    /// Converts the grid back to a sparse map, with the top left cell at `origin`. Cells equal
+    /// to `fill` are left out.
+    pub fn to_map(&self, origin: Point, fill: &T) -> HashMap<Point, T>
+    where
+        T: PartialEq,
+    {
+        self.enumerate()
+            .filter(|&(_, c)| c != fill)
+            .map(|(pt, c)| {
+                let pt = Point::new(origin.x + pt.x as i64, origin.y + pt.y as i64);
+                (pt, c.clone())
+            })
+            .collect()
+    }
+
+    pub fn transpose(&self) -> Self {
+        Self::from_fn(self.h, self.w, |x, y| self.at(y, x).clone()).with_wrap(self.wrap.swapped())
+    }
+
+    pub fn rotate_cw(&self) -> Self {
+        Self::from_fn(self.h, self.w, |x, y| self.at(y, self.h - 1 - x).clone())
+            .with_wrap(self.wrap.swapped())
+    }
+
+    pub fn rotate_ccw(&self) -> Self {
+        Self::from_fn(self.h, self.w, |x, y| self.at(self.w - 1 - y, x).clone())
+            .with_wrap(self.wrap.swapped())
+    }
+
+    pub fn rotate_180(&self) -> Self {
+        Self::from_fn(self.w, self.h, |x, y| {
+            self.at(self.w - 1 - x, self.h - 1 - y).clone()
+        })
+        .with_wrap(self.wrap)
+    }
+
+    /// Mirrors the grid left to right.
+    pub fn flip_h(&self) -> Self {
+        Self::from_fn(self.w, self.h, |x, y| self.at(self.w - 1 - x, y).clone())
+            .with_wrap(self.wrap)
+    }
+
+    /// Mirrors the grid top to bottom.
+    pub fn flip_v(&self) -> Self {
+        Self::from_fn(self.w, self.h, |x, y| self.at(x, self.h - 1 - y).clone())
+            .with_wrap(self.wrap)
+    }
+
+    pub fn oriented(&self, o: Orientation) -> Self {
+        let flipped = if o.flipped {
+            self.flip_h()
+        } else {
+            self.clone()
+        };
+        match o.rotations % 4 {
+            0 => flipped,
+            1 => flipped.rotate_cw(),
+            2 => flipped.rotate_180(),
+            _ => flipped.rotate_ccw(),
+        }
+    }
+
+    /// Iterates over the 8 rotations and reflections of the grid, in the order of
+    /// `Orientation::all()`.
+    pub fn orientations(&self) -> impl Iterator<Item = Self> + '_ {
+        Orientation::all().map(move |o| self.oriented(o))
+    }
+
+    /// Copies the part of the grid covered by `rect`, clipped to the grid bounds.
+    pub fn subgrid(&self, rect: Rect) -> Self {
+        let x0 = rect.x.min(self.w);
+        let y0 = rect.y.min(self.h);
+        let w = rect.w.min(self.w - x0);
+        let h = rect.h.min(self.h - y0);
+        Self::from_fn(w, h, |x, y| self.at(x0 + x, y0 + y).clone())
+    }
+
+    /// Returns the cells along one edge, read left to right or top to bottom.
+    pub fn border(&self, side: Side) -> Vec<&T> {
+        if self.w == 0 || self.h == 0 {
+            return vec![];
+        }
+        match side {
+            Side::Top => (0..self.w).map(|x| self.at(x, 0)).collect(),
+            Side::Bottom => (0..self.w).map(|x| self.at(x, self.h - 1)).collect(),
+            Side::Left => (0..self.h).map(|y| self.at(0, y)).collect(),
+            Side::Right => (0..self.h).map(|y| self.at(self.w - 1, y)).collect(),
+        }
+    }
+
+    /// Same as `find_pattern`, trying each of the 8 orientations of the pattern. A symmetric
+    /// pattern matches the same spot under several orientations.
+    pub fn find_pattern_oriented(&self, pattern: &Grid<Option<T>>) -> Vec<(Orientation, GridPoint)>
+    where
+        T: PartialEq,
+    {
+        Orientation::all()
+            .zip(pattern.orientations())
+            .flat_map(|(o, p)| self.find_pattern(&p).into_iter().map(move |pt| (o, pt)))
+            .collect()
+    }
+
+    /// Copies `other` into the grid with its top left corner at `at`. Cells falling outside
+    /// the grid are dropped, unless the grid wraps.
+    pub fn paste(&mut self, other: &Grid<T>, at: impl Coord) {
+        for (pt, cell) in other.enumerate() {
+            self.set((at.x() + pt.x, at.y() + pt.y), cell.clone());
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T> Grid<T> {
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn set_wrap(&mut self, wrap: Wrap) {
+        self.wrap = wrap;
+    }
+
+    pub fn wrap(&self) -> Wrap {
+        self.wrap
     }
 
     pub fn set(&mut self, c: impl Coord, value: T) {
-        if c.x() < self.w && c.y() < self.h {
-            if let Some(e) = self.cells.get_mut(c.x() + c.y() * self.w) {
-                *e = value;
-            }
+        if let Some(i) = self.index(&c) {
+            self.cells[i] = value;
         }
     }
 
     pub fn get(&self, c: impl Coord) -> Option<&T> {
-        if c.x() < self.w && c.y() < self.h {
-            self.cells.get(c.x() + c.y() * self.w)
-        } else {
-            None
-        }
+        self.index(&c).map(|i| &self.cells[i])
     }
 
     pub fn neighbours4(&self, c: impl Coord) -> Vec<&T> {
@@ -121,107 +409,1571 @@ where
         .collect()
     }
 
-    fn neighbour(&self, c: &impl Coord, dx: isize, dy: isize) -> Option<&T> {
-        if (c.x() == 0 && dx == -1) || (c.y() == 0 && dy == -1) {
-            None
-        } else {
-            let c = (
-                ((c.x() as isize) + dx) as usize,
-                ((c.y() as isize) + dy) as usize,
-            );
-            self.get(c)
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.cells.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.cells.iter_mut()
+    }
+
+    /// Iterates over every cell along with its position, row by row.
+    pub fn enumerate(&self) -> impl Iterator<Item = (GridPoint, &T)> {
+        let w = self.w;
+        self.cells
+            .iter()
+            .enumerate()
+            .map(move |(i, c)| (GridPoint { x: i % w, y: i / w }, c))
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        self.cells.chunks(self.w.max(1))
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = impl Iterator<Item = &T>> {
+        (0..self.w).map(move |x| self.cells.iter().skip(x).step_by(self.w))
+    }
+
+    pub fn count(&self, pred: impl Fn(&T) -> bool) -> usize {
+        self.cells.iter().filter(|&c| pred(c)).count()
+    }
+
+    pub fn positions<'a>(
+        &'a self,
+        pred: impl Fn(&T) -> bool + 'a,
+    ) -> impl Iterator<Item = GridPoint> + 'a {
+        self.enumerate()
+            .filter(move |(_, c)| pred(c))
+            .map(|(pt, _)| pt)
+    }
+
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> Grid<U> {
+        Grid {
+            cells: self.cells.iter().map(f).collect(),
+            w: self.w,
+            h: self.h,
+            wrap: self.wrap,
         }
     }
-}
 
-impl<T> FromStr for Grid<T>
-where
-    T: TryFrom<u8>,
-{
-    type Err = T::Error;
+    /// Returns the top left corners of every place where `pattern` matches the grid. `None`
+    /// cells in the pattern match anything. On a wrapping grid, matches may cross the edges.
+    /// An empty pattern matches nowhere.
+    pub fn find_pattern(&self, pattern: &Grid<Option<T>>) -> Vec<GridPoint>
+    where
+        T: PartialEq,
+    {
+        if pattern.w == 0 || pattern.h == 0 {
+            return vec![];
+        }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let cells = s
-            .lines()
-            .map(|l| l.bytes().map(T::try_from).collect::<Result<Vec<_>, _>>())
-            .collect::<Result<Vec<_>, _>>()?;
+        let max_x = if self.wrap.horizontal() {
+            self.w
+        } else {
+            (self.w + 1).saturating_sub(pattern.w)
+        };
+        let max_y = if self.wrap.vertical() {
+            self.h
+        } else {
+            (self.h + 1).saturating_sub(pattern.h)
+        };
 
-        let h = cells.len();
-        let w = cells.first().map_or(0, |c| c.len());
+        (0..max_y)
+            .flat_map(|y| (0..max_x).map(move |x| GridPoint { x, y }))
+            .filter(|at| self.matches_at(pattern, at))
+            .collect()
+    }
 
-        Ok(Grid {
-            cells: cells.into_iter().flatten().collect(),
-            w,
-            h,
+    fn matches_at(&self, pattern: &Grid<Option<T>>, at: &GridPoint) -> bool
+    where
+        T: PartialEq,
+    {
+        pattern.enumerate().all(|(pt, cell)| match cell {
+            Some(c) => self.get((at.x + pt.x, at.y + pt.y)) == Some(c),
+            None => true,
         })
     }
+
+    /// Walks the grid from `start` by steps of `(dx, dy)`, wrapping around according to the
+    /// grid's wrap mode. The walk ends when a step leaves the grid on an axis that doesn't wrap,
+    /// which means it never ends on a grid that wraps both ways.
+    pub fn walk(&self, start: impl Coord, dx: isize, dy: isize) -> Walk<'_, T> {
+        Walk {
+            grid: self,
+            pos: self.offset(&start, 0, 0),
+            dx,
+            dy,
+        }
+    }
+
+    fn neighbour(&self, c: &impl Coord, dx: isize, dy: isize) -> Option<&T> {
+        self.offset(c, dx, dy).and_then(|c| self.get(c))
+    }
+
+    fn offset(&self, c: &impl Coord, dx: isize, dy: isize) -> Option<(usize, usize)> {
+        let x = wrap_axis(c.x(), dx, self.w, self.wrap.horizontal())?;
+        let y = wrap_axis(c.y(), dy, self.h, self.wrap.vertical())?;
+        Some((x, y))
+    }
+
+    fn at(&self, x: usize, y: usize) -> &T {
+        &self.cells[x + y * self.w]
+    }
+
+    fn index(&self, c: &impl Coord) -> Option<usize> {
+        self.offset(c, 0, 0).map(|(x, y)| x + y * self.w)
+    }
 }
 
-impl<T> Display for Grid<T>
-where
-    T: Display,
-{
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        for row in self.cells.chunks(self.w) {
-            for cell in row {
-                write!(f, "{}", cell)?;
+#[allow(dead_code)]
+impl<T> Grid<T> {
+    /// Returns the positions of the orthogonal neighbours of a cell, wrapping according to the
+    /// grid's wrap mode.
+    pub fn neighbour_points4(&self, c: impl Coord) -> Vec<GridPoint> {
+        [(-1, 0), (0, -1), (0, 1), (1, 0)]
+            .iter()
+            .flat_map(|&(dx, dy)| self.offset(&c, dx, dy))
+            .map(|(x, y)| GridPoint { x, y })
+            .collect()
+    }
+
+    /// Computes the number of steps needed to reach every cell from `start`, moving
+    /// orthogonally through cells accepted by `passable`. Unreachable cells are `None`.
+    pub fn bfs(&self, start: impl Coord, passable: impl Fn(&T) -> bool) -> Grid<Option<usize>> {
+        let mut dist = Grid {
+            cells: vec![None; self.cells.len()],
+            w: self.w,
+            h: self.h,
+            wrap: self.wrap,
+        };
+        let start = match self.index(&start) {
+            Some(i) if passable(&self.cells[i]) => i,
+            _ => return dist,
+        };
+        let mut queue = VecDeque::new();
+        dist.cells[start] = Some(0);
+        queue.push_back(start);
+
+        while let Some(i) = queue.pop_front() {
+            let d = dist.cells[i].unwrap_or_default();
+            for n in self.neighbour_indices(i) {
+                if dist.cells[n].is_none() && passable(&self.cells[n]) {
+                    dist.cells[n] = Some(d + 1);
+                    queue.push_back(n);
+                }
             }
-            writeln!(f)?;
         }
-        Ok(())
+
+        dist
     }
-}
 
-pub trait Coord {
-    fn x(&self) -> usize;
-    fn y(&self) -> usize;
-    fn coords(&self) -> (usize, usize) {
-        (self.x(), self.y())
+    /// Finds a path with the fewest steps from `start` to `goal`, both included.
+    pub fn shortest_path(
+        &self,
+        start: impl Coord,
+        goal: impl Coord,
+        passable: impl Fn(&T) -> bool,
+    ) -> Option<Vec<GridPoint>> {
+        let dist = self.bfs(start, &passable);
+        let mut i = self.index(&goal)?;
+        let mut d = dist.cells[i]?;
+        let mut path = vec![self.point(i)];
+
+        // walk back from the goal, always stepping to a cell one step closer to the start
+        while d > 0 {
+            i = self
+                .neighbour_indices(i)
+                .into_iter()
+                .find(|&n| dist.cells[n] == Some(d - 1))?;
+            d -= 1;
+            path.push(self.point(i));
+        }
+        path.reverse();
+
+        Some(path)
     }
-}
 
-#[derive(Debug)]
-pub struct GridPoint {
-    pub x: usize,
-    pub y: usize,
-}
+    /// Finds the cheapest path from `start` to `goal`, where `cost` gives the price of entering
+    /// a cell, or `None` if the cell can't be entered. Returns the total cost and the path.
+    pub fn dijkstra(
+        &self,
+        start: impl Coord,
+        goal: impl Coord,
+        cost: impl Fn(&T) -> Option<u64>,
+    ) -> Option<(u64, Vec<GridPoint>)> {
+        self.cheapest_path(start, goal, cost, |_| 0)
+    }
 
-impl Coord for GridPoint {
-    fn x(&self) -> usize {
-        self.x
+    /// Same as `dijkstra`, guided by the Manhattan distance to the goal. The heuristic only
+    /// holds when every cell costs at least 1.
+    pub fn astar(
+        &self,
+        start: impl Coord,
+        goal: impl Coord,
+        cost: impl Fn(&T) -> Option<u64>,
+    ) -> Option<(u64, Vec<GridPoint>)> {
+        let (gx, gy) = goal.coords();
+        let manhattan = |pt: GridPoint| {
+            let dx = axis_distance(pt.x, gx, self.w, self.wrap.horizontal());
+            let dy = axis_distance(pt.y, gy, self.h, self.wrap.vertical());
+            (dx + dy) as u64
+        };
+        self.cheapest_path(start, goal, cost, manhattan)
     }
 
-    fn y(&self) -> usize {
-        self.y
+    /// Returns every cell reachable from `start` through passable cells, `start` included.
+    pub fn flood_fill(&self, start: impl Coord, passable: impl Fn(&T) -> bool) -> Vec<GridPoint> {
+        self.bfs(start, passable)
+            .positions(Option::is_some)
+            .collect()
+    }
+
+    /// Splits the passable cells into orthogonally connected regions.
+    pub fn components(&self, passable: impl Fn(&T) -> bool) -> Vec<Vec<GridPoint>> {
+        let mut seen = vec![false; self.cells.len()];
+        let mut components = vec![];
+
+        for start in 0..self.cells.len() {
+            if seen[start] || !passable(&self.cells[start]) {
+                continue;
+            }
+            let mut component = vec![];
+            let mut stack = vec![start];
+            seen[start] = true;
+            while let Some(i) = stack.pop() {
+                component.push(self.point(i));
+                for n in self.neighbour_indices(i) {
+                    if !seen[n] && passable(&self.cells[n]) {
+                        seen[n] = true;
+                        stack.push(n);
+                    }
+                }
+            }
+            components.push(component);
+        }
+
+        components
+    }
+
+    fn cheapest_path(
+        &self,
+        start: impl Coord,
+        goal: impl Coord,
+        cost: impl Fn(&T) -> Option<u64>,
+        heuristic: impl Fn(GridPoint) -> u64,
+    ) -> Option<(u64, Vec<GridPoint>)> {
+        let start = self.index(&start)?;
+        let goal = self.index(&goal)?;
+        cost(&self.cells[start])?;
+
+        let mut best = vec![u64::MAX; self.cells.len()];
+        let mut prev = vec![None; self.cells.len()];
+        let mut heap = BinaryHeap::new();
+        best[start] = 0;
+        heap.push(Reverse((heuristic(self.point(start)), 0, start)));
+
+        while let Some(Reverse((_, c, i))) = heap.pop() {
+            if i == goal {
+                let mut path = vec![self.point(i)];
+                let mut i = i;
+                while let Some(p) = prev[i] {
+                    path.push(self.point(p));
+                    i = p;
+                }
+                path.reverse();
+                return Some((c, path));
+            }
+            if c > best[i] {
+                continue;
+            }
+            for n in self.neighbour_indices(i) {
+                if let Some(step) = cost(&self.cells[n]) {
+                    let nc = c + step;
+                    if nc < best[n] {
+                        best[n] = nc;
+                        prev[n] = Some(i);
+                        heap.push(Reverse((nc + heuristic(self.point(n)), nc, n)));
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    fn neighbour_indices(&self, i: usize) -> Vec<usize> {
+        let pt = self.point(i);
+        self.neighbour_points4(pt)
+            .into_iter()
+            .map(|n| n.x + n.y * self.w)
+            .collect()
+    }
+
+    fn point(&self, i: usize) -> GridPoint {
+        GridPoint {
+            x: i % self.w,
+            y: i / self.w,
+        }
     }
 }
 
-impl Coord for &GridPoint {
-    fn x(&self) -> usize {
-        self.x
+fn axis_distance(a: usize, b: usize, len: usize, wrap: bool) -> usize {
+    let d = a.abs_diff(b);
+    if wrap {
+        d.min(len - d)
+    } else {
+        d
     }
+}
 
-    fn y(&self) -> usize {
-        self.y
+fn wrap_axis(v: usize, d: isize, len: usize, wrap: bool) -> Option<usize> {
+    let v = v as isize + d;
+    if len == 0 {
+        None
+    } else if wrap {
+        Some(v.rem_euclid(len as isize) as usize)
+    } else if v >= 0 && (v as usize) < len {
+        Some(v as usize)
+    } else {
+        None
     }
 }
 
-impl Coord for (usize, usize) {
-    fn x(&self) -> usize {
-        self.0
+pub struct Walk<'a, T> {
+    grid: &'a Grid<T>,
+    pos: Option<(usize, usize)>,
+    dx: isize,
+    dy: isize,
+}
+
+impl<'a, T> Iterator for Walk<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.pos?;
+        self.pos = self.grid.offset(&pos, self.dx, self.dy);
+        self.grid.get(pos)
     }
+}
 
-    fn y(&self) -> usize {
-        self.1
+impl<T> FromStr for Grid<T>
+where
+    T: TryFrom<u8>,
+{
+    type Err = ParseGridError<T::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_lines(s.lines())
     }
+}
 
-    fn coords(&self) -> (usize, usize) {
-        *self
+/// Builds a grid from lines of cells, checking that they all have the width of the first one.
+/// Empty lines at the end are ignored.
+fn parse_lines<'a, T>(
+    lines: impl Iterator<Item = &'a str>,
+) -> Result<Grid<T>, ParseGridError<T::Error>>
+where
+    T: TryFrom<u8>,
+{
+    let mut lines = lines.collect::<Vec<_>>();
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+
+    let h = lines.len();
+    let w = lines.first().map_or(0, |l| l.len());
+    let mut cells = Vec::with_capacity(w * h);
+
+    for (y, l) in lines.iter().enumerate() {
+        if l.len() != w {
+            return Err(ParseGridError::RaggedRow {
+                line: y + 1,
+                expected: w,
+                found: l.len(),
+            });
+        }
+        for (x, b) in l.bytes().enumerate() {
+            let cell = T::try_from(b).map_err(|error| ParseGridError::InvalidCell {
+                line: y + 1,
+                column: x + 1,
+                byte: b,
+                error,
+            })?;
+            cells.push(cell);
+        }
     }
+
+    Ok(Grid {
+        cells,
+        w,
+        h,
+        wrap: Wrap::None,
+    })
 }
 
-#[derive(Clone, Debug)]
-pub struct Point {
-    x: i64,
-    y: i64,
+const BINARY_TAG: &[u8; 4] = b"GRD1";
+
+#[allow(dead_code)]
+impl<T> Grid<T>
+where
+    T: ToByte,
+{
+    /// Writes the grid in a compact binary format: a `GRD1` tag, the width and height as
+    /// little-endian `u32`, the wrap mode as a byte, then one byte per cell, row by row.
+    /// Fails with `InvalidInput` if a dimension doesn't fit in a `u32`.
+    pub fn write_binary(&self, mut w: impl Write) -> io::Result<()> {
+        let dimension = |n: usize| {
+            u32::try_from(n).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("grid dimension {} doesn't fit in a u32", n),
+                )
+            })
+        };
+        let (width, height) = (dimension(self.w)?, dimension(self.h)?);
+
+        w.write_all(BINARY_TAG)?;
+        w.write_all(&width.to_le_bytes())?;
+        w.write_all(&height.to_le_bytes())?;
+        w.write_all(&[self.wrap as u8])?;
+        w.write_all(&self.cells.iter().map(T::to_byte).collect::<Vec<_>>())
+    }
+
+    /// Writes the grid as text: a `<width> <height> <wrap>` header line, then one line per row.
+    pub fn write_text(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "{} {} {}", self.w, self.h, self.wrap.name())?;
+        for row in self.rows() {
+            w.write_all(&row.iter().map(T::to_byte).collect::<Vec<_>>())?;
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+impl<T> Grid<T>
+where
+    T: TryFrom<u8>,
+{
+    pub fn read_binary(mut r: impl Read) -> Result<Self, ParseGridError<T::Error>> {
+        let mut header = [0; 13];
+        r.read_exact(&mut header).map_err(ParseGridError::Io)?;
+        if &header[..4] != BINARY_TAG {
+            return Err(ParseGridError::Header("missing GRD1 tag".into()));
+        }
+        let w = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+        let h = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        let wrap = *Wrap::ALL
+            .get(header[12] as usize)
+            .ok_or_else(|| ParseGridError::Header(format!("invalid wrap mode {}", header[12])))?;
+
+        let mut bytes = vec![];
+        r.take((w * h) as u64)
+            .read_to_end(&mut bytes)
+            .map_err(ParseGridError::Io)?;
+        if bytes.len() != w * h {
+            return Err(ParseGridError::Header(format!(
+                "expected {} cells, found {}",
+                w * h,
+                bytes.len()
+            )));
+        }
+
+        let cells = bytes
+            .into_iter()
+            .enumerate()
+            .map(|(i, b)| {
+                T::try_from(b).map_err(|error| ParseGridError::InvalidCell {
+                    line: i / w + 1,
+                    column: i % w + 1,
+                    byte: b,
+                    error,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { cells, w, h, wrap })
+    }
+
+    pub fn read_text(r: impl Read) -> Result<Self, ParseGridError<T::Error>> {
+        let lines = BufReader::new(r)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ParseGridError::Io)?;
+        let header = lines.first().map_or("", String::as_str);
+        let fields = header.split(' ').collect::<Vec<_>>();
+        let (w, h, wrap) = match fields.as_slice() {
+            [w, h, wrap] => (
+                w.parse::<usize>().ok(),
+                h.parse::<usize>().ok(),
+                Wrap::ALL.iter().find(|m| m.name() == *wrap),
+            ),
+            _ => (None, None, None),
+        };
+        let (w, h, wrap) = match (w, h, wrap) {
+            (Some(w), Some(h), Some(&wrap)) => (w, h, wrap),
+            _ => {
+                return Err(ParseGridError::Header(format!(
+                    "invalid header {:?}",
+                    header
+                )))
+            }
+        };
+
+        let grid: Self =
+            parse_lines(lines[1..].iter().map(String::as_str)).map_err(|e| match e {
+                ParseGridError::InvalidCell {
+                    line,
+                    column,
+                    byte,
+                    error,
+                } => ParseGridError::InvalidCell {
+                    line: line + 1,
+                    column,
+                    byte,
+                    error,
+                },
+                ParseGridError::RaggedRow {
+                    line,
+                    expected,
+                    found,
+                } => ParseGridError::RaggedRow {
+                    line: line + 1,
+                    expected,
+                    found,
+                },
+                e => e,
+            })?;
+        if grid.cells.len() != w * h || (h > 0 && grid.w != w) {
+            return Err(ParseGridError::Header(format!(
+                "expected {}x{} cells, found {}x{}",
+                w, h, grid.w, grid.h
+            )));
+        }
+
+        Ok(Self { w, h, ..grid }.with_wrap(wrap))
+    }
+}
+
+/// Error returned when parsing a grid, with 1-based positions in the input.
+#[derive(Debug)]
+pub enum ParseGridError<E> {
+    Io(io::Error),
+    Header(String),
+    InvalidCell {
+        line: usize,
+        column: usize,
+        byte: u8,
+        error: E,
+    },
+    RaggedRow {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl<E> Display for ParseGridError<E>
+where
+    E: Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            ParseGridError::Io(e) => write!(f, "{}", e),
+            ParseGridError::Header(e) => write!(f, "invalid header: {}", e),
+            ParseGridError::InvalidCell {
+                line,
+                column,
+                byte,
+                error,
+            } => write!(
+                f,
+                "line {}, column {}: invalid cell {:?}: {}",
+                line, column, *byte as char, error
+            ),
+            ParseGridError::RaggedRow {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected {} cells, found {}",
+                line, expected, found
+            ),
+        }
+    }
+}
+
+impl<E> std::error::Error for ParseGridError<E> where E: Debug + Display {}
+
+impl<T> Display for Grid<T>
+where
+    T: Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        for row in self.cells.chunks(self.w) {
+            for cell in row {
+                write!(f, "{}", cell)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// A grid of booleans packed 64 cells to a word, one run of words per row. Cells outside the
+/// grid are always dead, so it doesn't wrap.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct BitGrid {
+    words: Vec<u64>,
+    stride: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+/// A Life-like rule, giving as bit sets the neighbour counts for which a dead cell is born
+/// and a live cell survives.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LifeRule {
+    pub birth: u16,
+    pub survive: u16,
+}
+
+#[allow(dead_code)]
+impl LifeRule {
+    pub const CONWAY: LifeRule = LifeRule {
+        birth: 1 << 3,
+        survive: 1 << 2 | 1 << 3,
+    };
+
+    pub fn new(birth: &[usize], survive: &[usize]) -> Self {
+        let bits = |counts: &[usize]| counts.iter().fold(0, |acc, &n| acc | 1 << n);
+        Self {
+            birth: bits(birth),
+            survive: bits(survive),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl BitGrid {
+    pub fn new(w: usize, h: usize) -> Self {
+        let stride = w.div_ceil(64);
+        Self {
+            words: vec![0; stride * h],
+            stride,
+            w,
+            h,
+        }
+    }
+
+    pub fn from_grid<T>(grid: &Grid<T>, pred: impl Fn(&T) -> bool) -> Self {
+        let mut bits = Self::new(grid.w, grid.h);
+        for (pt, cell) in grid.enumerate() {
+            if pred(cell) {
+                bits.set(pt, true);
+            }
+        }
+        bits
+    }
+
+    pub fn get(&self, c: impl Coord) -> bool {
+        c.x() < self.w
+            && c.y() < self.h
+            && self.words[c.y() * self.stride + c.x() / 64] >> (c.x() % 64) & 1 == 1
+    }
+
+    pub fn set(&mut self, c: impl Coord, value: bool) {
+        if c.x() < self.w && c.y() < self.h {
+            let word = &mut self.words[c.y() * self.stride + c.x() / 64];
+            let bit = 1 << (c.x() % 64);
+            if value {
+                *word |= bit;
+            } else {
+                *word &= !bit;
+            }
+        }
+    }
+
+    pub fn row(&self, y: usize) -> &[u64] {
+        &self.words[y * self.stride..(y + 1) * self.stride]
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn invert(&self) -> Self {
+        let mut output = self.clone();
+        output.words.iter_mut().for_each(|w| *w = !*w);
+        output.clear_padding();
+        output
+    }
+
+    /// Moves every cell by `dx` columns and `dy` rows. Cells pushed past the edges are lost.
+    pub fn shifted(&self, dx: isize, dy: isize) -> Self {
+        let mut output = Self::new(self.w, self.h);
+        for y in 0..self.h {
+            let src_y = y as isize - dy;
+            if src_y < 0 || src_y >= self.h as isize {
+                continue;
+            }
+            let src = self.row(src_y as usize);
+            let dst = &mut output.words[y * self.stride..(y + 1) * self.stride];
+            shift_row(src, dst, dx);
+        }
+        output.clear_padding();
+        output
+    }
+
+    /// Computes the next generation under `rule`, counting the 8 neighbours of 64 cells at a
+    /// time with bit-sliced adders.
+    pub fn life_step(&self, rule: LifeRule) -> Self {
+        let mut output = Self::new(self.w, self.h);
+        let empty = vec![0; self.stride];
+
+        for y in 0..self.h {
+            let above = if y > 0 { self.row(y - 1) } else { &empty };
+            let below = if y + 1 < self.h {
+                self.row(y + 1)
+            } else {
+                &empty
+            };
+            let row = self.row(y);
+
+            for i in 0..self.stride {
+                let mut planes = [0u64; 4];
+                for r in [above, row, below].iter() {
+                    let prev = if i > 0 { r[i - 1] } else { 0 };
+                    let next = r.get(i + 1).copied().unwrap_or(0);
+                    add_bits(&mut planes, r[i] << 1 | prev >> 63);
+                    add_bits(&mut planes, r[i] >> 1 | next << 63);
+                }
+                add_bits(&mut planes, above[i]);
+                add_bits(&mut planes, below[i]);
+
+                let alive = row[i];
+                let mut born = 0;
+                let mut survives = 0;
+                for n in 0..=8 {
+                    let count_is_n = planes.iter().enumerate().fold(!0, |acc, (b, &p)| {
+                        acc & if n >> b & 1 == 1 { p } else { !p }
+                    });
+                    if rule.birth >> n & 1 == 1 {
+                        born |= count_is_n;
+                    }
+                    if rule.survive >> n & 1 == 1 {
+                        survives |= count_is_n;
+                    }
+                }
+                output.words[y * self.stride + i] = (alive & survives) | (!alive & born);
+            }
+        }
+
+        output.clear_padding();
+        output
+    }
+
+    fn clear_padding(&mut self) {
+        if !self.w.is_multiple_of(64) {
+            let mask = (1 << (self.w % 64)) - 1;
+            for row in self.words.chunks_mut(self.stride) {
+                if let Some(last) = row.last_mut() {
+                    *last &= mask;
+                }
+            }
+        }
+    }
+
+    fn combine(&self, other: &BitGrid, f: impl Fn(u64, u64) -> u64) -> Self {
+        assert_eq!((self.w, self.h), (other.w, other.h), "grid sizes differ");
+        Self {
+            words: self
+                .words
+                .iter()
+                .zip(other.words.iter())
+                .map(|(&a, &b)| f(a, b))
+                .collect(),
+            stride: self.stride,
+            w: self.w,
+            h: self.h,
+        }
+    }
+}
+
+/// Adds one bit per lane to a 4-bit counter stored as one word per bit.
+fn add_bits(planes: &mut [u64; 4], mut carry: u64) {
+    for plane in planes.iter_mut() {
+        let sum = *plane ^ carry;
+        carry &= *plane;
+        *plane = sum;
+    }
+}
+
+fn shift_row(src: &[u64], dst: &mut [u64], dx: isize) {
+    let words = dx.unsigned_abs() / 64;
+    let bits = dx.unsigned_abs() % 64;
+    for (i, d) in dst.iter_mut().enumerate() {
+        let word = |j: Option<usize>| j.and_then(|j| src.get(j)).copied().unwrap_or(0);
+        *d = if dx >= 0 {
+            let lo = word(i.checked_sub(words + 1));
+            let hi = word(i.checked_sub(words));
+            if bits == 0 {
+                hi
+            } else {
+                hi << bits | lo >> (64 - bits)
+            }
+        } else {
+            let lo = word(Some(i + words));
+            let hi = word(Some(i + words + 1));
+            if bits == 0 {
+                lo
+            } else {
+                lo >> bits | hi << (64 - bits)
+            }
+        };
+    }
+}
+
+impl BitAnd for &BitGrid {
+    type Output = BitGrid;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        self.combine(rhs, |a, b| a & b)
+    }
+}
+
+impl BitOr for &BitGrid {
+    type Output = BitGrid;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.combine(rhs, |a, b| a | b)
+    }
+}
+
+impl BitXor for &BitGrid {
+    type Output = BitGrid;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        self.combine(rhs, |a, b| a ^ b)
+    }
+}
+
+impl Display for BitGrid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        for y in 0..self.h {
+            for x in 0..self.w {
+                write!(f, "{}", if self.get((x, y)) { '#' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// One of the 8 symmetries of a grid: an optional left-right flip followed by a number of
+/// clockwise quarter turns.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Orientation {
+    pub flipped: bool,
+    pub rotations: u8,
+}
+
+#[allow(dead_code)]
+impl Orientation {
+    pub fn all() -> impl Iterator<Item = Orientation> {
+        [false, true]
+            .iter()
+            .flat_map(|&flipped| (0..4).map(move |rotations| Orientation { flipped, rotations }))
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Side {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+#[allow(dead_code)]
+impl Rect {
+    pub fn new(x: usize, y: usize, w: usize, h: usize) -> Self {
+        Self { x, y, w, h }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.w == 0 || self.h == 0
+    }
+
+    pub fn contains(&self, c: impl Coord) -> bool {
+        c.x() >= self.x && c.x() < self.x + self.w && c.y() >= self.y && c.y() < self.y + self.h
+    }
+
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.w).min(other.x + other.w);
+        let bottom = (self.y + self.h).min(other.y + other.h);
+        if x < right && y < bottom {
+            Some(Rect::new(x, y, right - x, bottom - y))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the smallest rectangle containing both rectangles.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        } else if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.w).max(other.x + other.w);
+        let bottom = (self.y + self.h).max(other.y + other.h);
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    /// Grows the rectangle by `margin` on every side, stopping at 0 on the top and left.
+    pub fn expand(&self, margin: usize) -> Rect {
+        let x = self.x.saturating_sub(margin);
+        let y = self.y.saturating_sub(margin);
+        Rect::new(
+            x,
+            y,
+            self.x + self.w + margin - x,
+            self.y + self.h + margin - y,
+        )
+    }
+
+    pub fn points(&self) -> impl Iterator<Item = GridPoint> {
+        let Rect { x, y, w, h } = *self;
+        (y..y + h).flat_map(move |y| (x..x + w).map(move |x| GridPoint { x, y }))
+    }
+}
+
+/// An axis-aligned box in `N` dimensions, with both corners included.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct BoundingBox<const N: usize> {
+    pub min: [isize; N],
+    pub max: [isize; N],
+}
+
+#[allow(dead_code)]
+impl<const N: usize> BoundingBox<N> {
+    pub fn from_point(pt: [isize; N]) -> Self {
+        Self { min: pt, max: pt }
+    }
+
+    /// Returns the smallest box containing every point, or `None` if there are none.
+    pub fn from_points(points: impl IntoIterator<Item = [isize; N]>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Self::from_point(first), |b, pt| b.include(pt)))
+    }
+
+    pub fn include(&self, pt: [isize; N]) -> Self {
+        self.union(&Self::from_point(pt))
+    }
+
+    pub fn contains(&self, pt: &[isize; N]) -> bool {
+        (0..N).all(|i| self.min[i] <= pt[i] && pt[i] <= self.max[i])
+    }
+
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let mut output = *self;
+        for i in 0..N {
+            output.min[i] = self.min[i].max(other.min[i]);
+            output.max[i] = self.max[i].min(other.max[i]);
+            if output.min[i] > output.max[i] {
+                return None;
+            }
+        }
+        Some(output)
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        let mut output = *self;
+        for i in 0..N {
+            output.min[i] = self.min[i].min(other.min[i]);
+            output.max[i] = self.max[i].max(other.max[i]);
+        }
+        output
+    }
+
+    pub fn expand(&self, margin: isize) -> Self {
+        let mut output = *self;
+        for i in 0..N {
+            output.min[i] -= margin;
+            output.max[i] += margin;
+        }
+        output
+    }
+
+    /// Iterates over every point of the box, the last coordinate changing fastest.
+    pub fn points(&self) -> impl Iterator<Item = [isize; N]> {
+        let b = *self;
+        let mut next = Some(b.min).filter(|pt| b.contains(pt));
+        std::iter::from_fn(move || {
+            let current = next?;
+            let mut pt = current;
+            next = None;
+            for i in (0..N).rev() {
+                if pt[i] < b.max[i] {
+                    pt[i] += 1;
+                    next = Some(pt);
+                    break;
+                }
+                pt[i] = b.min[i];
+            }
+            Some(current)
+        })
+    }
+}
+
+pub trait Coord {
+    fn x(&self) -> usize;
+    fn y(&self) -> usize;
+    fn coords(&self) -> (usize, usize) {
+        (self.x(), self.y())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GridPoint {
+    pub x: usize,
+    pub y: usize,
+}
+
+impl Coord for GridPoint {
+    fn x(&self) -> usize {
+        self.x
+    }
+
+    fn y(&self) -> usize {
+        self.y
+    }
+}
+
+impl Coord for &GridPoint {
+    fn x(&self) -> usize {
+        self.x
+    }
+
+    fn y(&self) -> usize {
+        self.y
+    }
+}
+
+impl Coord for (usize, usize) {
+    fn x(&self) -> usize {
+        self.0
+    }
+
+    fn y(&self) -> usize {
+        self.1
+    }
+
+    fn coords(&self) -> (usize, usize) {
+        *self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Point {
+    pub x: i64,
+    pub y: i64,
+}
+
+impl Point {
+    pub fn new(x: i64, y: i64) -> Self {
+        Self { x, y }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_get() {
+        let grid: Grid<u8> = Grid::from_str("123\n456").unwrap();
+        assert_eq!(grid.get((3, 0)), None);
+
+        let grid = grid.with_wrap(Wrap::Horizontal);
+        assert_eq!(grid.get((3, 0)), Some(&b'1'));
+        assert_eq!(grid.get((0, 2)), None);
+
+        let grid = grid.with_wrap(Wrap::Both);
+        assert_eq!(grid.get((4, 3)), Some(&b'5'));
+    }
+
+    #[test]
+    fn test_wrap_neighbours() {
+        let grid: Grid<u8> = Grid::from_str("123\n456\n789").unwrap();
+        assert_eq!(grid.neighbours4((0, 0)), vec![&b'4', &b'2']);
+
+        let grid = grid.with_wrap(Wrap::Vertical);
+        assert_eq!(grid.neighbours4((0, 0)), vec![&b'7', &b'4', &b'2']);
+
+        let grid = grid.with_wrap(Wrap::Both);
+        assert_eq!(grid.neighbours8((0, 0)).len(), 8);
+        assert_eq!(grid.neighbours4((0, 0)), vec![&b'3', &b'7', &b'4', &b'2']);
+    }
+
+    #[test]
+    fn test_iterators() {
+        let grid: Grid<u8> = Grid::from_str("123\n456").unwrap();
+        assert_eq!(grid.iter().count(), 6);
+
+        let rows: Vec<_> = grid.rows().collect();
+        assert_eq!(rows, vec![&b"123"[..], &b"456"[..]]);
+
+        let columns: Vec<Vec<_>> = grid.columns().map(|c| c.copied().collect()).collect();
+        assert_eq!(
+            columns,
+            vec![b"14".to_vec(), b"25".to_vec(), b"36".to_vec()]
+        );
+
+        let (pt, cell) = grid.enumerate().nth(4).unwrap();
+        assert_eq!((pt, cell), (GridPoint { x: 1, y: 1 }, &b'5'));
+
+        assert_eq!(grid.count(|&c| c % 2 == 0), 3);
+        let even: Vec<_> = grid.positions(|&c| c % 2 == 0).collect();
+        assert_eq!(
+            even,
+            vec![
+                GridPoint { x: 1, y: 0 },
+                GridPoint { x: 0, y: 1 },
+                GridPoint { x: 2, y: 1 }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let mut grid: Grid<u8> = Grid::from_str("12\n34").unwrap();
+        grid.iter_mut().for_each(|c| *c -= b'0');
+        assert_eq!(grid.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_transformations() {
+        let grid: Grid<u8> = Grid::from_str("123\n456").unwrap();
+        assert_eq!(grid.transpose(), Grid::from_str("14\n25\n36").unwrap());
+        assert_eq!(grid.rotate_cw(), Grid::from_str("41\n52\n63").unwrap());
+        assert_eq!(grid.rotate_ccw(), Grid::from_str("36\n25\n14").unwrap());
+        assert_eq!(grid.rotate_180(), Grid::from_str("654\n321").unwrap());
+        assert_eq!(grid.flip_h(), Grid::from_str("321\n654").unwrap());
+        assert_eq!(grid.flip_v(), Grid::from_str("456\n123").unwrap());
+        assert_eq!(grid.rotate_cw().rotate_ccw(), grid);
+    }
+
+    #[test]
+    fn test_orientations() {
+        let grid: Grid<u8> = Grid::from_str("12\n34").unwrap();
+        let all: Vec<_> = grid.orientations().collect();
+        assert_eq!(all.len(), 8);
+        for (i, a) in all.iter().enumerate() {
+            assert!(all[i + 1..].iter().all(|b| a != b));
+        }
+        assert!(all.contains(&grid.transpose()));
+        assert!(all.contains(&grid.flip_v()));
+    }
+
+    #[test]
+    fn test_subgrid_border_paste() {
+        let mut grid: Grid<u8> = Grid::from_str("123\n456\n789").unwrap();
+        assert_eq!(
+            grid.subgrid(Rect::new(1, 1, 5, 5)),
+            Grid::from_str("56\n89").unwrap()
+        );
+        assert_eq!(grid.border(Side::Top), vec![&b'1', &b'2', &b'3']);
+        assert_eq!(grid.border(Side::Right), vec![&b'3', &b'6', &b'9']);
+        assert_eq!(grid.border(Side::Bottom), vec![&b'7', &b'8', &b'9']);
+        assert_eq!(grid.border(Side::Left), vec![&b'1', &b'4', &b'7']);
+
+        let patch: Grid<u8> = Grid::from_str("ab\ncd").unwrap();
+        grid.paste(&patch, (2, 1));
+        assert_eq!(grid, Grid::from_str("123\n45a\n78c").unwrap());
+    }
+
+    const MAZE: &str = "..#....\n.##.##.\n....#..\n##.##.#\n.......";
+
+    #[test]
+    fn test_bfs() {
+        let grid: Grid<u8> = Grid::from_str(MAZE).unwrap();
+        let dist = grid.bfs((0, 0), |&c| c == b'.');
+        assert_eq!(dist.get((0, 0)), Some(&Some(0)));
+        assert_eq!(dist.get((3, 0)), Some(&Some(7)));
+        assert_eq!(dist.get((2, 0)), Some(&None));
+
+        let path = grid.shortest_path((0, 0), (3, 0), |&c| c == b'.').unwrap();
+        assert_eq!(path.len(), 8);
+        assert_eq!(path.first(), Some(&GridPoint { x: 0, y: 0 }));
+        assert_eq!(path.last(), Some(&GridPoint { x: 3, y: 0 }));
+        assert!(path.iter().all(|pt| grid.get(pt) == Some(&b'.')));
+    }
+
+    #[test]
+    fn test_weighted_paths() {
+        let grid: Grid<u8> = Grid::from_str("1163\n1381\n2136\n3694").unwrap();
+        let cost = |&c: &u8| Some((c - b'0') as u64);
+
+        let (c, path) = grid.dijkstra((0, 0), (3, 3), cost).unwrap();
+        assert_eq!(c, 17);
+        assert_eq!(path.first(), Some(&GridPoint { x: 0, y: 0 }));
+        assert_eq!(path.last(), Some(&GridPoint { x: 3, y: 3 }));
+        let path_cost: u64 = path[1..]
+            .iter()
+            .flat_map(|pt| grid.get(pt))
+            .flat_map(cost)
+            .sum();
+        assert_eq!(path_cost, 17);
+
+        let (c, _) = grid.astar((0, 0), (3, 3), cost).unwrap();
+        assert_eq!(c, 17);
+
+        let walls = |&c: &u8| if c == b'8' { None } else { Some(1) };
+        assert_eq!(grid.astar((0, 0), (2, 1), walls), None);
+    }
+
+    #[test]
+    fn test_components() {
+        let grid: Grid<u8> = Grid::from_str("#..\n###\n.#.").unwrap();
+        let mut sizes: Vec<_> = grid
+            .components(|&c| c == b'#')
+            .iter()
+            .map(Vec::len)
+            .collect();
+        sizes.sort_unstable();
+        assert_eq!(sizes, vec![5]);
+
+        assert_eq!(grid.components(|&c| c == b'.').len(), 3);
+        assert_eq!(grid.flood_fill((1, 0), |&c| c == b'.').len(), 2);
+    }
+
+    fn mask(s: &str) -> Grid<Option<u8>> {
+        let grid: Grid<u8> = Grid::from_str(s).unwrap();
+        grid.map(|&c| if c == b'#' { Some(c) } else { None })
+    }
+
+    #[test]
+    fn test_find_pattern() {
+        let grid: Grid<u8> = Grid::from_str("#.#..\n.#...\n#.#.#\n...#.\n..#.#").unwrap();
+        let cross = mask("#.#\n.#.\n#.#");
+        assert_eq!(
+            grid.find_pattern(&cross),
+            vec![GridPoint { x: 0, y: 0 }, GridPoint { x: 2, y: 2 }]
+        );
+
+        let grid: Grid<u8> = Grid::from_str(".#..#\n#....\n.#..#").unwrap();
+        assert_eq!(grid.find_pattern(&cross), vec![]);
+        let grid = grid.with_wrap(Wrap::Horizontal);
+        assert_eq!(grid.find_pattern(&cross), vec![GridPoint { x: 4, y: 0 }]);
+
+        let empty: Grid<Option<u8>> = Grid::new(0, 0);
+        assert_eq!(grid.with_wrap(Wrap::None).find_pattern(&empty), vec![]);
+    }
+
+    #[test]
+    fn test_find_pattern_oriented() {
+        let grid: Grid<u8> = Grid::from_str("....\n.#..\n.##.\n....").unwrap();
+        let corner = mask("#.\n##");
+        assert_eq!(grid.find_pattern(&corner), vec![GridPoint { x: 1, y: 1 }]);
+
+        let found = grid.flip_h().find_pattern_oriented(&corner);
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().all(|(o, _)| o.flipped || o.rotations != 0));
+        assert!(found.iter().all(|(_, pt)| pt == &GridPoint { x: 1, y: 1 }));
+    }
+
+    fn naive_life(grid: &Grid<u8>) -> Grid<u8> {
+        Grid::from_fn(grid.w, grid.h, |x, y| {
+            let n = grid
+                .neighbours8((x, y))
+                .iter()
+                .filter(|&&&c| c == b'#')
+                .count();
+            match (grid.get((x, y)), n) {
+                (Some(b'#'), 2) | (_, 3) => b'#',
+                _ => b'.',
+            }
+        })
+    }
+
+    #[test]
+    fn test_bitgrid_life() {
+        let mut grid = Grid::from_fn(130, 5, |x, y| {
+            if (x * 7 + y * 3) % 5 < 2 || x == 63 || x == 64 {
+                b'#'
+            } else {
+                b'.'
+            }
+        });
+        let mut bits = BitGrid::from_grid(&grid, |&c| c == b'#');
+
+        for _ in 0..4 {
+            grid = naive_life(&grid);
+            bits = bits.life_step(LifeRule::CONWAY);
+            assert_eq!(bits, BitGrid::from_grid(&grid, |&c| c == b'#'));
+            assert_eq!(bits.count_ones(), grid.count(|&c| c == b'#'));
+        }
+        assert_eq!(LifeRule::new(&[3], &[2, 3]), LifeRule::CONWAY);
+    }
+
+    #[test]
+    fn test_bitgrid_ops() {
+        let grid: Grid<u8> = Grid::from_str("#..#\n.##.").unwrap();
+        let a = BitGrid::from_grid(&grid, |&c| c == b'#');
+        let b = a.shifted(1, 0);
+        assert_eq!(b.to_string(), ".#..\n..##\n");
+        assert_eq!(a.shifted(-1, 1).to_string(), "....\n..#.\n");
+        assert_eq!((&a & &b).to_string(), "....\n..#.\n");
+        assert_eq!((&a | &b).to_string(), "##.#\n.###\n");
+        assert_eq!((&a ^ &b).to_string(), "##.#\n.#.#\n");
+        assert_eq!(a.invert().count_ones(), 4);
+
+        let mut wide = BitGrid::new(200, 1);
+        wide.set((10, 0), true);
+        assert!(wide.shifted(130, 0).get((140, 0)));
+        assert!(wide.shifted(130, 0).shifted(-135, 0).get((5, 0)));
+        assert_eq!(wide.shifted(190, 0).count_ones(), 0);
+    }
+
+    #[derive(Debug)]
+    struct Bit;
+
+    impl TryFrom<u8> for Bit {
+        type Error = String;
+
+        fn try_from(value: u8) -> Result<Self, Self::Error> {
+            match value {
+                b'1' => Ok(Bit),
+                _ => Err("not a bit".into()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let grid: Result<Grid<u8>, _> = Grid::from_str("123\n45\n678");
+        assert!(matches!(
+            grid,
+            Err(ParseGridError::RaggedRow {
+                line: 2,
+                expected: 3,
+                found: 2
+            })
+        ));
+
+        let grid: Result<Grid<Bit>, _> = Grid::from_str("11\n1x\n");
+        let err = grid.unwrap_err();
+        assert!(matches!(
+            err,
+            ParseGridError::InvalidCell {
+                line: 2,
+                column: 2,
+                byte: b'x',
+                ..
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 2: invalid cell 'x': not a bit"
+        );
+    }
+
+    #[test]
+    fn test_parse_trailing_lines() {
+        let grid: Grid<u8> = Grid::from_reader("12\n34\n\n".as_bytes()).unwrap();
+        assert_eq!((grid.w, grid.h), (2, 2));
+
+        let grid: Grid<u8> = Grid::from_str("12\r\n34\r\n").unwrap();
+        assert_eq!(grid.rows().collect::<Vec<_>>(), vec![b"12", b"34"]);
+
+        let grid: Result<Grid<u8>, _> = Grid::from_str("12\n\n34");
+        assert!(matches!(
+            grid,
+            Err(ParseGridError::RaggedRow { line: 2, .. })
+        ));
+    }
+
+    grid_cell! {
+        #[derive(Clone, Debug, Eq, PartialEq)]
+        enum Tile {
+            Open = '.',
+            Wall = '#',
+            Door = 'D',
+        }
+    }
+
+    #[test]
+    fn test_grid_cell() {
+        let grid: Grid<Tile> = Grid::from_str("#.D\n.##").unwrap();
+        assert_eq!(grid.get((2, 0)), Some(&Tile::Door));
+        assert_eq!(grid.to_string(), "#.D\n.##\n");
+        assert_eq!(Tile::default(), Tile::Open);
+
+        let err = Tile::try_from(b'x').unwrap_err();
+        assert_eq!(err, "expected one of ., #, D");
+    }
+
+    #[test]
+    fn test_from_map() {
+        let points: HashMap<_, _> = vec![(Point::new(3, 5), b'a'), (Point::new(5, 4), b'b')]
+            .into_iter()
+            .collect();
+        let (grid, origin) = Grid::from_map(points.clone(), b'.');
+        assert_eq!(origin, Point::new(3, 4));
+        assert_eq!(grid, Grid::from_str("..b\na..").unwrap());
+        assert_eq!(grid.to_map(origin, &b'.'), points);
+
+        let points: HashMap<_, _> = vec![(Point::new(-2, -1), 1), (Point::new(0, 0), 2)]
+            .into_iter()
+            .collect();
+        let (grid, origin) = Grid::from_map(points, 0);
+        assert_eq!((grid.w, grid.h), (3, 2));
+        assert_eq!(grid.get((2, 1)), Some(&2));
+        assert_eq!(origin, Point::new(-2, -1));
+
+        let (grid, _) = Grid::from_map(HashMap::new(), 0);
+        assert_eq!((grid.w, grid.h), (0, 0));
+    }
+
+    #[test]
+    fn test_rect() {
+        let a = Rect::new(1, 1, 3, 2);
+        let b = Rect::new(3, 2, 4, 4);
+        assert!(a.contains((3, 2)));
+        assert!(!a.contains((4, 1)));
+        assert_eq!(a.intersection(&b), Some(Rect::new(3, 2, 1, 1)));
+        assert_eq!(a.intersection(&Rect::new(4, 1, 2, 2)), None);
+        assert_eq!(a.union(&b), Rect::new(1, 1, 6, 5));
+        assert_eq!(a.expand(2), Rect::new(0, 0, 6, 5));
+        assert_eq!(a.points().count(), 6);
+        assert_eq!(a.points().last(), Some(GridPoint { x: 3, y: 2 }));
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let b = BoundingBox::from_points(vec![[1, -2, 0], [3, 0, 0], [2, 1, -1]]).unwrap();
+        assert_eq!(b.min, [1, -2, -1]);
+        assert_eq!(b.max, [3, 1, 0]);
+        assert_eq!(b.points().count(), 3 * 4 * 2);
+        assert!(b.points().all(|pt| b.contains(&pt)));
+
+        let around = BoundingBox::from_point([0, 0]).expand(1);
+        let points: Vec<_> = around.points().collect();
+        assert_eq!(points.len(), 9);
+        assert_eq!(points[..2], [[-1, -1], [-1, 0]]);
+
+        assert_eq!(around.intersection(&BoundingBox::from_point([1, 5])), None);
+        assert_eq!(around.union(&BoundingBox::from_point([1, 5])).max, [1, 5]);
+        assert_eq!(BoundingBox::<2>::from_points(vec![]), None);
+    }
+
+    #[test]
+    fn test_binary_format() {
+        let grid: Grid<Tile> = Grid::from_str("#.D\n.##").unwrap();
+        let grid = grid.with_wrap(Wrap::Vertical);
+
+        let mut bytes = vec![];
+        grid.write_binary(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 4 + 4 + 4 + 1 + 6);
+        assert_eq!(Grid::read_binary(&bytes[..]).unwrap(), grid);
+
+        let huge: Grid<Tile> = Grid {
+            cells: vec![],
+            w: 1 << 32,
+            h: 0,
+            wrap: Wrap::None,
+        };
+        let err = huge.write_binary(&mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let truncated = Grid::<Tile>::read_binary(&bytes[..15]);
+        assert!(matches!(truncated, Err(ParseGridError::Header(_))));
+
+        bytes[14] = b'x';
+        let invalid = Grid::<Tile>::read_binary(&bytes[..]);
+        assert!(matches!(
+            invalid,
+            Err(ParseGridError::InvalidCell {
+                line: 1,
+                column: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_text_format() {
+        let grid: Grid<Tile> = Grid::from_str("#.D\n.##").unwrap();
+        let grid = grid.with_wrap(Wrap::Both);
+
+        let mut text = vec![];
+        grid.write_text(&mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text.clone()).unwrap(),
+            "3 2 both\n#.D\n.##\n"
+        );
+        assert_eq!(Grid::read_text(&text[..]).unwrap(), grid);
+
+        let empty: Grid<u8> = Grid::from_fn(0, 0, |_, _| 0);
+        let mut text = vec![];
+        empty.write_text(&mut text).unwrap();
+        assert_eq!(Grid::read_text(&text[..]).unwrap(), empty);
+
+        let wrong_size = Grid::<Tile>::read_text("3 3 none\n#.D\n.##\n".as_bytes());
+        assert!(matches!(wrong_size, Err(ParseGridError::Header(_))));
+        let ragged = Grid::<Tile>::read_text("3 2 none\n#.D\n.#\n".as_bytes());
+        assert!(matches!(
+            ragged,
+            Err(ParseGridError::RaggedRow { line: 3, .. })
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let grid: Grid<u8> = Grid::from_str("12\n34").unwrap();
+        let grid = grid.with_wrap(Wrap::Horizontal);
+        let json = serde_json::to_string(&grid).unwrap();
+        assert_eq!(
+            json,
+            r#"{"cells":[49,50,51,52],"w":2,"h":2,"wrap":"Horizontal"}"#
+        );
+        assert_eq!(serde_json::from_str::<Grid<u8>>(&json).unwrap(), grid);
+
+        let bad = r#"{"cells":[49,50,51],"w":2,"h":2,"wrap":"None"}"#;
+        assert!(serde_json::from_str::<Grid<u8>>(bad).is_err());
+    }
+
+    #[test]
+    fn test_walk() {
+        let grid: Grid<u8> = Grid::from_str("12\n34\n56").unwrap();
+        let cells: Vec<_> = grid.walk((0, 0), 1, 1).collect();
+        assert_eq!(cells, vec![&b'1', &b'4']);
+
+        let grid = grid.with_wrap(Wrap::Horizontal);
+        let cells: Vec<_> = grid.walk((0, 0), 1, 1).collect();
+        assert_eq!(cells, vec![&b'1', &b'4', &b'5']);
+
+        let grid = grid.with_wrap(Wrap::Both);
+        assert_eq!(grid.walk((0, 0), 0, 1).take(5).count(), 5);
+    }
 }
@@ -0,0 +1,193 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    str::FromStr,
+};
+
+/// A hexagon on a pointy-top hex grid, in axial coordinates. The third cube coordinate is
+/// `s = -q - r`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Hex {
+    pub q: i64,
+    pub r: i64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Direction {
+    E,
+    SE,
+    SW,
+    W,
+    NW,
+    NE,
+}
+
+#[allow(dead_code)]
+impl Hex {
+    pub const ORIGIN: Hex = Hex { q: 0, r: 0 };
+
+    pub fn new(q: i64, r: i64) -> Self {
+        Self { q, r }
+    }
+
+    pub fn from_cube(x: i64, y: i64, z: i64) -> Option<Self> {
+        if x + y + z == 0 {
+            Some(Self { q: x, r: z })
+        } else {
+            None
+        }
+    }
+
+    pub fn s(&self) -> i64 {
+        -self.q - self.r
+    }
+
+    pub fn cube(&self) -> (i64, i64, i64) {
+        (self.q, self.s(), self.r)
+    }
+
+    pub fn step(self, d: Direction) -> Self {
+        let (dq, dr) = d.offset();
+        Self {
+            q: self.q + dq,
+            r: self.r + dr,
+        }
+    }
+
+    pub fn follow(self, path: &[Direction]) -> Self {
+        path.iter().fold(self, |h, &d| h.step(d))
+    }
+
+    pub fn neighbours(self) -> impl Iterator<Item = Hex> {
+        Direction::ALL.iter().map(move |&d| self.step(d))
+    }
+
+    pub fn distance(self, other: Hex) -> i64 {
+        ((self.q - other.q).abs() + (self.r - other.r).abs() + (self.s() - other.s()).abs()) / 2
+    }
+}
+
+impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::E,
+        Direction::SE,
+        Direction::SW,
+        Direction::W,
+        Direction::NW,
+        Direction::NE,
+    ];
+
+    fn offset(self) -> (i64, i64) {
+        match self {
+            Direction::E => (1, 0),
+            Direction::SE => (0, 1),
+            Direction::SW => (-1, 1),
+            Direction::W => (-1, 0),
+            Direction::NW => (0, -1),
+            Direction::NE => (1, -1),
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "e" => Ok(Self::E),
+            "se" => Ok(Self::SE),
+            "sw" => Ok(Self::SW),
+            "w" => Ok(Self::W),
+            "nw" => Ok(Self::NW),
+            "ne" => Ok(Self::NE),
+            _ => Err(format!("Unknown direction: {}", s).into()),
+        }
+    }
+}
+
+/// Parses a run of directions, either packed together like `esenee` or separated by commas
+/// and spaces like `e, se, ne`.
+#[allow(dead_code)]
+pub fn parse_path(s: &str) -> Result<Vec<Direction>, Box<dyn Error>> {
+    let mut path = vec![];
+    let mut rest = s.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+
+    while !rest.is_empty() {
+        let len = if rest.starts_with('n') || rest.starts_with('s') {
+            2
+        } else {
+            1
+        };
+        let token = rest.get(..len).ok_or("Truncated direction")?;
+        path.push(token.parse()?);
+        rest = rest[len..].trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+    }
+
+    Ok(path)
+}
+
+/// Computes the next generation of a sparse hexagonal automaton. `rule` is given whether a
+/// cell is alive and how many of its neighbours are, and tells whether it lives on.
+#[allow(dead_code)]
+pub fn step(live: &HashSet<Hex>, rule: impl Fn(bool, usize) -> bool) -> HashSet<Hex> {
+    let mut counts: HashMap<Hex, usize> = live.iter().map(|&h| (h, 0)).collect();
+    for h in live {
+        for n in h.neighbours() {
+            *counts.entry(n).or_default() += 1;
+        }
+    }
+
+    counts
+        .into_iter()
+        .filter(|&(h, n)| rule(live.contains(&h), n))
+        .map(|(h, _)| h)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path() {
+        let path = parse_path("esenee").unwrap();
+        assert_eq!(
+            path,
+            vec![Direction::E, Direction::SE, Direction::NE, Direction::E]
+        );
+        assert_eq!(parse_path("e, se, sw, w, nw, ne").unwrap(), Direction::ALL);
+        assert!(parse_path("ex").is_err());
+        assert!(parse_path("en").is_err());
+    }
+
+    #[test]
+    fn test_follow() {
+        let path = parse_path("nwwswee").unwrap();
+        assert_eq!(Hex::ORIGIN.follow(&path), Hex::ORIGIN);
+
+        let path = parse_path("esew").unwrap();
+        assert_eq!(Hex::ORIGIN.follow(&path), Hex::ORIGIN.step(Direction::SE));
+    }
+
+    #[test]
+    fn test_distance() {
+        let h = Hex::new(3, -1);
+        assert_eq!(Hex::ORIGIN.distance(h), 3);
+        assert!(Hex::ORIGIN
+            .neighbours()
+            .all(|n| n.distance(Hex::ORIGIN) == 1));
+        assert_eq!(Hex::from_cube(3, -2, -1), Some(h));
+        assert_eq!(h.cube(), (3, -2, -1));
+        assert_eq!(Hex::from_cube(1, 1, 1), None);
+    }
+
+    #[test]
+    fn test_step() {
+        let live: HashSet<_> = vec![Hex::ORIGIN, Hex::new(1, 0)].into_iter().collect();
+        let rule = |alive, n| matches!((alive, n), (true, 1) | (_, 2));
+        let next = step(&live, rule);
+        assert_eq!(next.len(), 4);
+        assert!(next.contains(&Hex::new(1, -1)));
+        assert!(next.contains(&Hex::new(0, 1)));
+    }
+}
@@ -1,15 +1,76 @@
 use crate::solutions::exec_day;
+use crate::solver::{enable_output, input_file};
 use std::env;
+use std::path::Path;
+use std::process;
 
+mod console;
+mod cycle;
+mod expr;
 mod grid;
+mod hex;
+mod render;
 mod solutions;
 mod solver;
+mod visualize;
+
+const USAGE: &str = "\
+usage: aoc-rs-2020 [<day>] [--visualize] [--fps <n>] [--output <dir>]
+       aoc-rs-2020 debug|disasm|cfg [<file>]";
+
+/// Reports a command line mistake and exits.
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(2);
+}
 
 fn main() {
-    let day = env::args()
-        .nth(1)
-        .unwrap_or_else(|| String::from("1"))
-        .parse()
-        .unwrap_or(1);
-    exec_day(day);
+    let mut args = env::args().skip(1);
+    let mut day = None;
+    let mut visualize = false;
+    let mut fps = visualize::DEFAULT_FPS;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "debug" => {
+                let path = args.next().unwrap_or_else(|| input_file(8));
+                if let Err(e) = console::debugger::run(&path) {
+                    eprintln!("{}", e);
+                }
+                return;
+            }
+            "disasm" | "cfg" => {
+                let path = args.next().unwrap_or_else(|| input_file(8));
+                match console::asm::load(&path) {
+                    Ok(program) if arg == "disasm" => {
+                        print!("{}", console::asm::disassemble(&program))
+                    }
+                    Ok(program) => print!("{}", console::asm::to_dot(&program)),
+                    Err(e) => eprintln!("{}", e),
+                }
+                return;
+            }
+            "--visualize" => visualize = true,
+            "--output" => match args.next() {
+                Some(dir) => enable_output(Path::new(&dir)),
+                None => usage_error("--output needs a directory"),
+            },
+            "--fps" => match args.next().map(|s| s.parse()) {
+                Some(Ok(n)) if n > 0 => fps = n,
+                Some(_) => usage_error("--fps needs a positive number"),
+                None => usage_error("--fps needs a value"),
+            },
+            s if s.starts_with('-') => usage_error(&format!("unknown option {}", s)),
+            s => match (day, s.parse()) {
+                (None, Ok(d)) => day = Some(d),
+                (Some(_), Ok(_)) => usage_error("only one day can be run at a time"),
+                (_, Err(_)) => usage_error(&format!("invalid day {:?}", s)),
+            },
+        }
+    }
+
+    if visualize {
+        visualize::enable(fps);
+    }
+    exec_day(day.unwrap_or(1));
 }
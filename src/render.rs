@@ -0,0 +1,175 @@
+use crate::grid::Grid;
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{self, Write},
+    time::Duration,
+};
+
+pub type Rgb = [u8; 3];
+
+/// Colour used for pixels that aren't covered by any cell, e.g. when frames have different
+/// sizes.
+const BACKGROUND: Rgb = [0, 0, 0];
+
+/// Maps a cell value to the colour it's drawn with.
+pub trait Colour {
+    fn colour(&self) -> Rgb;
+}
+
+impl Colour for bool {
+    fn colour(&self) -> Rgb {
+        if *self {
+            [255, 255, 255]
+        } else {
+            BACKGROUND
+        }
+    }
+}
+
+/// Draws grids as images, each cell as a `scale` by `scale` square of pixels.
+#[derive(Clone, Copy, Debug)]
+pub struct Renderer {
+    scale: usize,
+}
+
+#[allow(dead_code)]
+impl Renderer {
+    pub fn new(scale: usize) -> Self {
+        Self {
+            scale: scale.max(1),
+        }
+    }
+
+    /// Writes a binary (P6) PPM image.
+    pub fn write_ppm<T: Colour>(&self, grid: &Grid<T>, mut w: impl Write) -> io::Result<()> {
+        let (width, height) = self.size(grid);
+        writeln!(w, "P6")?;
+        writeln!(w, "{} {}", width, height)?;
+        writeln!(w, "255")?;
+        w.write_all(&self.pixels(grid, width, height).concat())
+    }
+
+    pub fn write_png<T: Colour>(
+        &self,
+        grid: &Grid<T>,
+        w: impl Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let (width, height) = self.size(grid);
+        let mut encoder = png::Encoder::new(w, width as u32, height as u32);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels(grid, width, height).concat())?;
+        Ok(())
+    }
+
+    /// Writes a looping animated GIF showing one frame per grid. The image is large enough for
+    /// the biggest frame, smaller ones are padded at the bottom and right.
+    pub fn write_gif<T: Colour>(
+        &self,
+        frames: &[Grid<T>],
+        frame_delay: Duration,
+        w: impl Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let width = frames.iter().map(|g| self.size(g).0).max().unwrap_or(0);
+        let height = frames.iter().map(|g| self.size(g).1).max().unwrap_or(0);
+        if width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err("Image too large for a GIF".into());
+        }
+
+        let frames = frames
+            .iter()
+            .map(|g| self.pixels(g, width, height))
+            .collect::<Vec<_>>();
+
+        // GIF images are indexed, so every colour in the animation goes into a global palette
+        let mut palette: HashMap<Rgb, u8> = HashMap::new();
+        let mut palette_bytes = vec![];
+        for &c in frames.iter().flatten() {
+            if !palette.contains_key(&c) {
+                if palette.len() == 256 {
+                    return Err("Too many colours for a GIF".into());
+                }
+                palette.insert(c, palette.len() as u8);
+                palette_bytes.extend_from_slice(&c);
+            }
+        }
+
+        let mut encoder = gif::Encoder::new(w, width as u16, height as u16, &palette_bytes)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        for pixels in frames {
+            let indices = pixels.iter().map(|c| palette[c]).collect::<Vec<_>>();
+            let mut frame =
+                gif::Frame::from_indexed_pixels(width as u16, height as u16, &indices, None);
+            frame.delay = (frame_delay.as_millis() / 10) as u16;
+            encoder.write_frame(&frame)?;
+        }
+
+        Ok(())
+    }
+
+    fn size<T>(&self, grid: &Grid<T>) -> (usize, usize) {
+        (grid.w * self.scale, grid.h * self.scale)
+    }
+
+    fn pixels<T: Colour>(&self, grid: &Grid<T>, width: usize, height: usize) -> Vec<Rgb> {
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(
+                    grid.get((x / self.scale, y / self.scale))
+                        .map_or(BACKGROUND, Colour::colour),
+                );
+            }
+        }
+        pixels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(cells: &[bool], w: usize) -> Grid<bool> {
+        Grid::from_fn(w, cells.len() / w, |x, y| cells[x + y * w])
+    }
+
+    #[test]
+    fn test_ppm() {
+        let mut out = vec![];
+        Renderer::new(2)
+            .write_ppm(&grid(&[true, false], 2), &mut out)
+            .unwrap();
+
+        let header = b"P6\n4 2\n255\n";
+        assert_eq!(&out[..header.len()], header);
+        let pixels = &out[header.len()..];
+        assert_eq!(pixels.len(), 4 * 2 * 3);
+        assert_eq!(
+            &pixels[..12],
+            &[255, 255, 255, 255, 255, 255, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_png() {
+        let mut out = vec![];
+        Renderer::new(1)
+            .write_png(&grid(&[true, false, false, true], 2), &mut out)
+            .unwrap();
+        assert_eq!(&out[..8], b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn test_gif() {
+        let frames = vec![grid(&[true], 1), grid(&[false, true, true, false], 2)];
+        let mut out = vec![];
+        Renderer::new(3)
+            .write_gif(&frames, Duration::from_millis(100), &mut out)
+            .unwrap();
+        assert_eq!(&out[..6], b"GIF89a");
+        assert_eq!(&out[6..10], &[6, 0, 6, 0]);
+        assert_eq!(out.last(), Some(&0x3b));
+    }
+}
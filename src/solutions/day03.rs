@@ -1,6 +1,5 @@
-use crate::grid::{Grid, GridPoint};
+use crate::grid::{grid_cell, Grid, Wrap};
 use crate::solver::Solver;
-use std::convert::TryFrom;
 use std::io::Read;
 
 pub struct Problem;
@@ -11,7 +10,7 @@ impl Solver for Problem {
     type Output2 = usize;
 
     fn parse_input<R: Read>(&self, r: R) -> Self::Input {
-        Grid::from_reader(r).unwrap()
+        Grid::from_reader(r).unwrap().with_wrap(Wrap::Horizontal)
     }
 
     fn solve_first(&self, input: &Self::Input) -> Self::Output1 {
@@ -27,46 +26,17 @@ impl Solver for Problem {
     }
 }
 
-fn check_slope(input: &Grid<Slope>, dx: usize, dy: usize) -> usize {
-    let mut tree_counter = 0;
-    let mut pt = GridPoint { x: 0, y: 0 };
-
-    while pt.y < input.h {
-        if pt.x >= input.w {
-            pt.x -= input.w;
-        }
-
-        if let Some(&Slope::Tree) = input.get(&pt) {
-            tree_counter += 1;
-        }
-
-        pt.x += dx;
-        pt.y += dy;
-    }
-
-    tree_counter
-}
-
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub enum Slope {
-    Empty,
-    Tree,
-}
-
-impl TryFrom<u8> for Slope {
-    type Error = String;
-
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            b'.' => Ok(Self::Empty),
-            b'#' => Ok(Self::Tree),
-            v => Err(format!("Invalid cell: {}", v)),
-        }
-    }
+fn check_slope(input: &Grid<Slope>, dx: isize, dy: isize) -> usize {
+    input
+        .walk((0, 0), dx, dy)
+        .filter(|&s| s == &Slope::Tree)
+        .count()
 }
 
-impl Default for Slope {
-    fn default() -> Self {
-        Self::Empty
+grid_cell! {
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub enum Slope {
+        Empty = '.',
+        Tree = '#',
     }
 }
@@ -1,16 +1,18 @@
-use crate::solver::{ReadExt, Solver};
-use lazy_static::lazy_static;
-use regex::Regex;
-use std::error::Error;
-use std::io::Read;
-use std::str::FromStr;
+use crate::console::trace::Tracer;
+use crate::console::{Halt, Instr, Vm};
+use crate::solver::{output_dir, ReadExt, Solver};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufWriter, Read};
+use std::path::Path;
 
 pub struct Problem;
 
 impl Solver for Problem {
     type Input = Vec<Instr>;
     type Output1 = i32;
-    type Output2 = i32;
+    type Output2 = Outcome;
 
     fn parse_input<R: Read>(&self, r: R) -> Self::Input {
         r.split_lines()
@@ -21,96 +23,175 @@ impl Solver for Problem {
     }
 
     fn solve_second(&self, input: &Self::Input) -> Self::Output2 {
-        (0..input.len())
-            .flat_map(|i| alter_program_at_index(input, i))
-            .find_map(|p| match exec_program(&p) {
-                (acc, ExitStatus::Ok) => Some(acc),
-                (_, ExitStatus::Loop) => None,
-            })
-            .unwrap_or_default()
+        if let (acc, Halt::Terminated) = exec_program(input) {
+            return Outcome::Terminates(acc);
+        }
+        let repair = match repair_program(input) {
+            Some(repair) => repair,
+            None => return Outcome::Unfixable,
+        };
+        if let Some(dir) = output_dir() {
+            if let Err(e) = write_traces(input, repair.index, &dir) {
+                eprintln!("Couldn't write traces: {}", e);
+            }
+        }
+        Outcome::Repaired(repair)
     }
 }
 
-#[derive(Clone)]
-pub enum Instr {
-    Nop(isize),
-    Jmp(isize),
-    Acc(i32),
+#[derive(Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// The program already terminates, with this value in the accumulator.
+    Terminates(i32),
+    Repaired(Repair),
+    /// No single `nop` or `jmp` flip makes the program terminate.
+    Unfixable,
 }
 
-impl FromStr for Instr {
-    type Err = Box<dyn Error>;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        lazy_static! {
-            static ref RE: Regex = Regex::new(r"^(jmp|acc|nop) ([-+]\d+)$").unwrap();
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Terminates(acc) => write!(f, "{} (no fix needed)", acc),
+            Outcome::Repaired(repair) => repair.fmt(f),
+            Outcome::Unfixable => write!(f, "no single instruction fix"),
         }
-        let caps = RE.captures(s).ok_or("No match")?;
+    }
+}
 
-        match &caps[1] {
-            "nop" => Ok(Self::Nop(caps[2].parse()?)),
-            "acc" => Ok(Self::Acc(caps[2].parse()?)),
-            "jmp" => Ok(Self::Jmp(caps[2].parse()?)),
-            _ => Err("Unknown instruction".into()),
-        }
+/// The instruction that had to be flipped for the program to terminate, and the final value of
+/// the accumulator.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Repair {
+    pub index: usize,
+    pub acc: i32,
+}
+
+impl Display for Repair {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (flipped instruction {})", self.acc, self.index)
     }
 }
 
-enum ExitStatus {
-    Ok,
-    Loop,
+fn exec_program(program: &[Instr]) -> (i32, Halt) {
+    let mut vm = Vm::new(program);
+    let halt = vm.run();
+    (vm.registers().acc, halt)
 }
 
-fn exec_program(program: &[Instr]) -> (i32, ExitStatus) {
-    let mut ip = 0;
-    let mut acc = 0;
-    let mut exec_map = vec![false; program.len()];
+/// Writes the traces of the original and the repaired program to `dir`, then tells where they
+/// start to differ.
+fn write_traces(program: &[Instr], index: usize, dir: &Path) -> io::Result<()> {
+    let mut patched = program.to_vec();
+    patched[index] = program[index].flipped().expect("not a nop or jmp");
+
+    let mut traces = vec![];
+    for (name, program) in &[("original", program), ("patched", &patched)] {
+        let mut tracer = Tracer::new(program);
+        tracer.run();
+        let path = dir.join(format!("day08-{}.jsonl", name));
+        tracer
+            .trace()
+            .write_jsonl(BufWriter::new(File::create(&path)?))?;
+        eprintln!(
+            "Wrote {} steps to {}",
+            tracer.trace().steps.len(),
+            path.display()
+        );
+        traces.push(tracer.into_trace());
+    }
+    if let Some(step) = traces[0].divergence(&traces[1]) {
+        eprintln!("Traces diverge at step {}", step);
+    }
+    Ok(())
+}
 
-    loop {
-        if let Some(&exec) = exec_map.get(ip) {
-            if exec {
-                return (acc, ExitStatus::Loop);
-            }
-        } else {
-            return (acc, ExitStatus::Ok);
+/// Finds the one `nop` or `jmp` to flip for the program to terminate, in linear time.
+///
+/// Walking the jumps backwards from the end of the program gives every instruction that leads
+/// to termination. The fix is then the first instruction on the original (looping) path that
+/// lands in that set once flipped.
+fn repair_program(program: &[Instr]) -> Option<Repair> {
+    let end = program.len();
+    let mut predecessors = vec![vec![]; end + 1];
+    for (i, instr) in program.iter().enumerate() {
+        let target = instr.successor(i);
+        if target >= 0 && target as usize <= end {
+            predecessors[target as usize].push(i);
         }
+    }
 
-        exec_map[ip] = true;
-
-        match &program[ip] {
-            Instr::Nop(_) => {
-                ip += 1;
-            }
-            Instr::Jmp(offset) => {
-                ip = ((ip as isize) + offset) as usize;
-            }
-            Instr::Acc(value) => {
-                acc += value;
-                ip += 1;
+    let mut terminates = vec![false; end + 1];
+    let mut stack = vec![end];
+    terminates[end] = true;
+    while let Some(i) = stack.pop() {
+        for &p in &predecessors[i] {
+            if !terminates[p] {
+                terminates[p] = true;
+                stack.push(p);
             }
         }
     }
-}
 
-fn alter_program_at_index(program: &[Instr], i: usize) -> Option<Vec<Instr>> {
-    match program.get(i) {
-        Some(&Instr::Nop(offset)) => {
-            let mut prg_clone = program.to_vec();
-            prg_clone[i] = Instr::Jmp(offset);
-            Some(prg_clone)
-        }
-        Some(&Instr::Jmp(offset)) => {
-            let mut prg_clone = program.to_vec();
-            prg_clone[i] = Instr::Nop(offset);
-            Some(prg_clone)
+    let mut vm = Vm::new(program);
+    while vm.halted().is_none() {
+        let ip = vm.registers().ip;
+        let target = program[ip]
+            .flipped()
+            .map(|f| f.successor(ip))
+            .filter(|&t| t >= 0 && terminates.get(t as usize) == Some(&true));
+        if let Some(target) = target {
+            // flipping a nop or jmp leaves acc as is, then the path from the target is the
+            // one known to terminate
+            let mut acc = vm.registers().acc;
+            let mut i = target as usize;
+            while i < end {
+                if let Instr::Acc(value) = program[i] {
+                    acc = acc.checked_add(value)?;
+                }
+                i = program[i].successor(i) as usize;
+            }
+            return Some(Repair { index: ip, acc });
         }
-        _ => None,
+        vm.step();
     }
+    None
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::console::{parse_program, SAMPLE};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_repair_program() {
+        let program = parse_program(SAMPLE);
+        assert_eq!(repair_program(&program), Some(Repair { index: 7, acc: 8 }));
+
+        let program = vec![Instr::Jmp(0)];
+        assert_eq!(repair_program(&program), Some(Repair { index: 0, acc: 0 }));
+
+        let program = vec![Instr::Acc(1), Instr::Jmp(-1)];
+        assert_eq!(repair_program(&program), Some(Repair { index: 1, acc: 1 }));
+
+        let program = vec![Instr::Acc(1), Instr::Jmp(-1), Instr::Jmp(-1)];
+        assert_eq!(repair_program(&program), None);
+
+        assert_eq!(repair_program(&[]), None);
+
+        let program = vec![Instr::Acc(i32::MAX), Instr::Jmp(0), Instr::Acc(1)];
+        assert_eq!(repair_program(&program), None);
+    }
+
+    #[test]
+    fn test_solve_second() {
+        let program = vec![Instr::Acc(2), Instr::Nop(5)];
+        assert_eq!(Problem.solve_second(&program), Outcome::Terminates(2));
+
+        let program = vec![Instr::Acc(1), Instr::Jmp(-1), Instr::Jmp(-1)];
+        assert_eq!(Problem.solve_second(&program), Outcome::Unfixable);
+        assert_eq!(Outcome::Unfixable.to_string(), "no single instruction fix");
+    }
 
     #[test]
     fn test_instr() {
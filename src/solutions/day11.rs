@@ -1,9 +1,10 @@
-use crate::grid::{Coord, Grid};
-use crate::solver::Solver;
-use std::convert::TryFrom;
-use std::fmt;
-use std::fmt::{Display, Formatter};
-use std::io::Read;
+use crate::cycle;
+use crate::grid::{grid_cell, Coord, Grid};
+use crate::render::{Colour, Renderer, Rgb};
+use crate::solver::{output_dir, Solver};
+use std::fs::File;
+use std::io::{BufWriter, Read};
+use std::time::Duration;
 
 pub struct Problem;
 
@@ -17,75 +18,76 @@ impl Solver for Problem {
     }
 
     fn solve_first(&self, input: &Self::Input) -> Self::Output1 {
-        let mut state = input.clone();
-        let mut occupied = input.n_occupied();
-        loop {
-            state = state.next_gen();
-            let cur_occ = state.n_occupied();
-            if cur_occ != occupied {
-                occupied = cur_occ;
-            } else {
-                break;
-            }
-        }
-
-        occupied
+        let mut frames = Frames::new();
+        let stable = cycle::find(input.clone(), |state| {
+            self.show_step(state);
+            frames.push(state);
+            state.next_gen()
+        });
+        frames.write("day11-part1.gif");
+
+        stable.state.n_occupied()
     }
 
     fn solve_second(&self, input: &Self::Input) -> Self::Output2 {
-        let mut state = input.clone();
-        let mut occupied = input.n_occupied();
-        loop {
-            state = state.next_gen_v2();
-            let cur_occ = state.n_occupied();
-            if cur_occ != occupied {
-                occupied = cur_occ;
-            } else {
-                break;
-            }
-        }
-
-        occupied
+        let mut frames = Frames::new();
+        let stable = cycle::find(input.clone(), |state| {
+            self.show_step(state);
+            frames.push(state);
+            state.next_gen_v2()
+        });
+        frames.write("day11-part2.gif");
+
+        stable.state.n_occupied()
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub enum Seat {
-    Floor,
-    Empty,
-    Occupied,
-}
+/// Generations kept to be written as an animated GIF, only when there's an output directory.
+struct Frames(Option<Vec<Grid<Seat>>>);
 
-impl Default for Seat {
-    fn default() -> Self {
-        Self::Floor
+impl Frames {
+    fn new() -> Self {
+        Frames(output_dir().map(|_| vec![]))
     }
-}
 
-impl TryFrom<u8> for Seat {
-    type Error = String;
+    fn push(&mut self, state: &Grid<Seat>) {
+        if let Some(frames) = &mut self.0 {
+            frames.push(state.clone());
+        }
+    }
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            b'.' => Ok(Self::Floor),
-            b'L' => Ok(Self::Empty),
-            b'#' => Ok(Self::Occupied),
-            _ => Err("Invalid value".into()),
+    fn write(&self, name: &str) {
+        let (frames, dir) = match (&self.0, output_dir()) {
+            (Some(frames), Some(dir)) => (frames, dir),
+            _ => return,
+        };
+        let path = dir.join(name);
+        let result = File::create(&path).map_err(|e| e.into()).and_then(|f| {
+            Renderer::new(4).write_gif(frames, Duration::from_millis(100), BufWriter::new(f))
+        });
+        match result {
+            Ok(()) => eprintln!("Wrote {} generations to {}", frames.len(), path.display()),
+            Err(e) => eprintln!("Couldn't write {}: {}", path.display(), e),
         }
     }
 }
 
-impl Display for Seat {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Seat::Floor => '.',
-                Seat::Empty => 'L',
-                Seat::Occupied => '#',
-            }
-        )
+grid_cell! {
+    #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+    pub enum Seat {
+        Floor = '.',
+        Empty = 'L',
+        Occupied = '#',
+    }
+}
+
+impl Colour for Seat {
+    fn colour(&self) -> Rgb {
+        match self {
+            Seat::Floor => [40, 40, 40],
+            Seat::Empty => [60, 160, 60],
+            Seat::Occupied => [220, 60, 40],
+        }
     }
 }
 
@@ -171,16 +173,7 @@ impl Grid<Seat> {
     }
 
     fn n_occupied(&self) -> usize {
-        let mut occupied = 0;
-        for y in 0..self.h {
-            for x in 0..self.w {
-                if let Some(Seat::Occupied) = self.get((x, y)) {
-                    occupied += 1;
-                }
-            }
-        }
-
-        occupied
+        self.count(|s| s == &Seat::Occupied)
     }
 
     fn actual_neighbours(&self, c: impl Coord) -> Vec<&Seat> {
@@ -1,9 +1,11 @@
-use crate::solver::{ReadExt, Solver};
+use crate::solver::{output_dir, ReadExt, Solver};
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::error::Error;
-use std::io::Read;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
 use std::str::FromStr;
 
 pub struct Problem;
@@ -11,7 +13,7 @@ pub struct Problem;
 impl Solver for Problem {
     type Input = Vec<Instruction>;
     type Output1 = u64;
-    type Output2 = u64;
+    type Output2 = u128;
 
     fn parse_input<R: Read>(&self, r: R) -> Self::Input {
         r.split_lines()
@@ -22,6 +24,11 @@ impl Solver for Problem {
     }
 
     fn solve_second(&self, input: &Self::Input) -> Self::Output2 {
+        if let Some(dir) = output_dir() {
+            if let Err(e) = write_dumps(input, &dir) {
+                eprintln!("Couldn't write memory dumps: {}", e);
+            }
+        }
         execute_program_v2(&input)
     }
 }
@@ -45,7 +52,10 @@ impl FromStr for Instruction {
             let mask = Mask::from_str(&c[1])?;
             Ok(Self::SetMask(mask))
         } else if let Some(c) = WRITEMEM_RE.captures(s) {
-            let address = c[1].parse()?;
+            let address: usize = c[1].parse()?;
+            if address >> BITS != 0 {
+                return Err(format!("address {} doesn't fit in {} bits", address, BITS).into());
+            }
             let value = c[2].parse()?;
             Ok(Self::WriteMemory(address, value))
         } else {
@@ -54,96 +64,278 @@ impl FromStr for Instruction {
     }
 }
 
+/// Masks and addresses are 36 bits wide.
+const BITS: usize = 36;
+
 #[derive(Debug)]
-pub struct Mask(Vec<Option<u8>>);
+pub struct Mask {
+    /// Bits of the value that are kept, the `X`s.
+    and_mask: u64,
+    /// Bits that are set, the `1`s.
+    or_mask: u64,
+    /// Bits of the address that take both values, the `X`s again.
+    float_mask: u64,
+}
 
 impl FromStr for Mask {
     type Err = Box<dyn Error>;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Mask(
-            s.bytes()
-                .map(|b| match b {
-                    b'1' => Some(1),
-                    b'0' => Some(0),
-                    _ => None,
-                })
-                .collect(),
-        ))
+        if s.len() != BITS {
+            return Err(format!("mask must be {} bits, got {}", BITS, s.len()).into());
+        }
+
+        let mut mask = Mask {
+            and_mask: 0,
+            or_mask: 0,
+            float_mask: 0,
+        };
+        for b in s.bytes() {
+            mask.or_mask <<= 1;
+            mask.float_mask <<= 1;
+            match b {
+                b'1' => mask.or_mask |= 1,
+                b'0' => {}
+                b'X' => mask.float_mask |= 1,
+                _ => return Err(format!("invalid mask bit {:?}", b as char).into()),
+            }
+        }
+        mask.and_mask = mask.float_mask;
+        Ok(mask)
     }
 }
 
 impl Mask {
     fn apply(&self, val: u64) -> u64 {
-        let mut output = 0;
-        for (i, bitmask) in self.0.iter().enumerate() {
-            output <<= 1;
-            let bit = self.0.len() - (i + 1);
-            if let Some(bitmask) = *bitmask {
-                output |= bitmask as u64;
-            } else {
-                let v = (val >> bit) & 1;
-                output |= v;
-            }
+        val & self.and_mask | self.or_mask
+    }
+
+    /// The addresses a write to `address` goes to, as a cube.
+    fn cube(&self, address: u64) -> Cube {
+        Cube {
+            fixed: (address | self.or_mask) & !self.float_mask,
+            floating: self.float_mask,
+        }
+    }
+}
+
+/// A set of addresses: the bits set in `floating` take both values, the others are those of
+/// `fixed`. Floating bits are always clear in `fixed`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+struct Cube {
+    fixed: u64,
+    floating: u64,
+}
+
+impl Cube {
+    fn len(&self) -> u128 {
+        1 << self.floating.count_ones()
+    }
+
+    fn intersection(&self, other: &Cube) -> Option<Cube> {
+        let both_fixed = !self.floating & !other.floating;
+        if (self.fixed ^ other.fixed) & both_fixed != 0 {
+            return None;
         }
-        output
+        let floating = self.floating & other.floating;
+        Some(Cube {
+            fixed: (self.fixed | other.fixed) & !floating,
+            floating,
+        })
     }
 
-    fn addresses(&self, address: u64) -> Vec<u64> {
-        let mut queue = VecDeque::new();
+    /// Enumerates the addresses in increasing order.
+    fn addresses(self) -> impl Iterator<Item = u64> {
+        let mut next = Some(0u64);
+        std::iter::from_fn(move || {
+            let sub = next?;
+            let following = sub.wrapping_sub(self.floating) & self.floating;
+            next = if following == 0 {
+                None
+            } else {
+                Some(following)
+            };
+            Some(self.fixed | sub)
+        })
+    }
+}
+
+fn execute_program(program: &[Instruction]) -> u64 {
+    Memory::run(program, Decoder::V1).sum()
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Decoder {
+    /// The mask applies to values.
+    V1,
+    /// The mask applies to addresses.
+    V2,
+}
+
+/// A value in memory and the index of the instruction that wrote it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Cell {
+    value: u64,
+    writer: usize,
+}
 
-        queue.push_back(0);
+/// Memory once a program ran, step by step, keeping track of what wrote where.
+#[derive(Debug, Default)]
+struct Memory {
+    cells: BTreeMap<u64, Cell>,
+    /// For each `SetMask` instruction, how many distinct addresses were written while it was
+    /// active.
+    mask_addresses: Vec<(usize, u128)>,
+}
 
-        for (i, bitmask) in self.0.iter().enumerate() {
-            let bit = self.0.len() - (i + 1);
-            let current_address_bit = (address >> bit) & 1;
+impl Memory {
+    fn run(program: &[Instruction], decoder: Decoder) -> Self {
+        let mut memory = Memory::default();
+        let mut current_mask = None;
 
-            let n = queue.len();
-            for _ in 0..n {
-                if let Some(mut addr) = queue.pop_front() {
-                    addr <<= 1;
-                    if let Some(bitmask) = *bitmask {
-                        if bitmask == 1 {
-                            addr |= 1;
-                        } else {
-                            addr |= current_address_bit;
+        for (i, instr) in program.iter().enumerate() {
+            match instr {
+                Instruction::SetMask(m) => {
+                    current_mask = Some(m);
+                    memory.mask_addresses.push((i, 0));
+                }
+                Instruction::WriteMemory(address, value) => match (decoder, current_mask) {
+                    (Decoder::V1, m) => {
+                        let value = m.map_or(0, |m| m.apply(*value));
+                        memory.write(*address as u64, value, i);
+                    }
+                    (Decoder::V2, Some(m)) => {
+                        for addr in m.cube(*address as u64).addresses() {
+                            memory.write(addr, *value, i);
                         }
-                        queue.push_back(addr);
-                    } else {
-                        queue.push_back(addr);
-                        addr |= 1;
-                        queue.push_back(addr);
                     }
-                }
+                    (Decoder::V2, None) => {}
+                },
+            }
+        }
+
+        memory
+    }
+
+    fn write(&mut self, address: u64, value: u64, writer: usize) {
+        let previous = self.cells.insert(address, Cell { value, writer });
+        if let Some((mask, n)) = self.mask_addresses.last_mut() {
+            // an address last written after the mask was set was already counted
+            if previous.is_none_or(|c| c.writer < *mask) {
+                *n += 1;
             }
         }
+    }
+
+    fn sum(&self) -> u64 {
+        self.cells.values().map(|c| c.value).sum()
+    }
+
+    fn write_text<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for (address, cell) in &self.cells {
+            writeln!(
+                w,
+                "mem[{}] = {} (instruction {})",
+                address, cell.value, cell.writer
+            )?;
+        }
+        for (i, n) in &self.mask_addresses {
+            writeln!(w, "mask (instruction {}) touched {} addresses", i, n)?;
+        }
+        Ok(())
+    }
 
-        queue.into()
+    fn write_json<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let cells = self.cells.iter().map(|(address, cell)| {
+            format!(
+                r#"{{"address":{},"value":{},"writer":{}}}"#,
+                address, cell.value, cell.writer
+            )
+        });
+        let masks = self
+            .mask_addresses
+            .iter()
+            .map(|(i, n)| format!(r#"{{"instruction":{},"addresses":{}}}"#, i, n));
+        writeln!(
+            w,
+            r#"{{"memory":[{}],"masks":[{}]}}"#,
+            cells.collect::<Vec<_>>().join(","),
+            masks.collect::<Vec<_>>().join(",")
+        )
+    }
+
+    /// Lists the addresses whose value differs between the two memories, with the value on
+    /// each side if there is one.
+    fn diff(&self, other: &Memory) -> Vec<(u64, Option<u64>, Option<u64>)> {
+        let addresses = self.cells.keys().chain(other.cells.keys());
+        addresses
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|a| {
+                let value = |m: &Memory| m.cells.get(a).map(|c| c.value);
+                (*a, value(self), value(other))
+            })
+            .filter(|(_, a, b)| a != b)
+            .collect()
     }
 }
 
-fn execute_program(program: &[Instruction]) -> u64 {
-    let mut memory = vec![0u64; 65536];
-    let mut current_mask = None;
+/// Decoding addresses may write to more of them than is reasonable to dump.
+const DUMP_LIMIT: u128 = 1 << 20;
 
+/// Writes the memory left by both decoders as text and JSON to `dir`, and their differences.
+fn write_dumps(program: &[Instruction], dir: &Path) -> io::Result<()> {
+    let mut current_mask = None;
+    let mut v2_writes = 0;
     for instr in program {
         match instr {
-            Instruction::SetMask(m) => {
-                current_mask = Some(m);
-            }
-            Instruction::WriteMemory(address, value) => {
-                if let Some(v) = memory.get_mut(*address) {
-                    *v = current_mask.map_or(0, |m| m.apply(*value));
-                }
+            Instruction::SetMask(m) => current_mask = Some(m),
+            Instruction::WriteMemory(address, _) => {
+                v2_writes += current_mask.map_or(0, |m| m.cube(*address as u64).len())
             }
         }
     }
+    if v2_writes > DUMP_LIMIT {
+        eprintln!(
+            "Not dumping memory, the program writes {} addresses",
+            v2_writes
+        );
+        return Ok(());
+    }
+
+    let mut memories = vec![];
+    for (name, decoder) in &[("v1", Decoder::V1), ("v2", Decoder::V2)] {
+        let memory = Memory::run(program, *decoder);
+        memory.write_text(BufWriter::new(File::create(
+            dir.join(format!("day14-{}.txt", name)),
+        )?))?;
+        memory.write_json(BufWriter::new(File::create(
+            dir.join(format!("day14-{}.json", name)),
+        )?))?;
+        memories.push(memory);
+    }
 
-    memory.iter().sum()
+    let path = dir.join("day14-diff.txt");
+    let mut w = BufWriter::new(File::create(&path)?);
+    let show = |v: Option<u64>| v.map_or("-".to_string(), |v| v.to_string());
+    let diff = memories[0].diff(&memories[1]);
+    for (address, a, b) in &diff {
+        writeln!(w, "mem[{}]: {} -> {}", address, show(*a), show(*b))?;
+    }
+    eprintln!(
+        "Wrote {} differing addresses to {}",
+        diff.len(),
+        path.display()
+    );
+    Ok(())
 }
 
-fn execute_program_v2(program: &[Instruction]) -> u64 {
-    let mut memory: HashMap<u64, u64> = HashMap::new();
+/// Sums memory after decoding addresses, without enumerating floating bits.
+///
+/// Every write is kept as a cube with a weight, by inclusion–exclusion: a new write cancels the
+/// part of every earlier cube it overlaps by adding their intersection with the opposite weight.
+fn execute_program_v2(program: &[Instruction]) -> u128 {
+    let mut cubes: HashMap<Cube, i128> = HashMap::new();
     let mut current_mask = None;
 
     for instr in program {
@@ -153,15 +345,26 @@ fn execute_program_v2(program: &[Instruction]) -> u64 {
             }
             Instruction::WriteMemory(address, value) => {
                 if let Some(m) = current_mask {
-                    for addr in m.addresses(*address as u64) {
-                        memory.insert(addr, *value);
+                    let cube = m.cube(*address as u64);
+                    let overlaps = cubes
+                        .iter()
+                        .filter_map(|(c, &weight)| Some((c.intersection(&cube)?, weight)))
+                        .collect::<Vec<_>>();
+                    for (c, weight) in overlaps {
+                        *cubes.entry(c).or_insert(0) -= weight;
                     }
+                    *cubes.entry(cube).or_insert(0) += i128::from(*value);
+                    cubes.retain(|_, weight| *weight != 0);
                 }
             }
         }
     }
 
-    memory.values().sum()
+    let total: i128 = cubes
+        .iter()
+        .map(|(c, weight)| weight * c.len() as i128)
+        .sum();
+    total as u128
 }
 
 #[cfg(test)]
@@ -174,14 +377,104 @@ mod tests {
         assert_eq!(m.apply(11), 73);
         assert_eq!(m.apply(101), 101);
         assert_eq!(m.apply(0), 64);
+        assert_eq!(m.apply(1 << 40 | 3), 65);
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(Mask::from_str("X1X").is_err());
+        assert!(Mask::from_str("XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX02").is_err());
+        assert!(Instruction::from_str("mem[68719476735] = 1").is_ok());
+        assert!(Instruction::from_str("mem[68719476736] = 1").is_err());
     }
 
     #[test]
     fn test_mask_addresses() {
         let m = Mask::from_str("000000000000000000000000000000X1001X").unwrap();
-        assert_eq!(m.addresses(42), vec![26, 27, 58, 59]);
+        let addresses = m.cube(42).addresses().collect::<Vec<_>>();
+        assert_eq!(addresses, vec![26, 27, 58, 59]);
 
         let m = Mask::from_str("00000000000000000000000000000000X0XX").unwrap();
-        assert_eq!(m.addresses(26), vec![16, 17, 18, 19, 24, 25, 26, 27]);
+        let addresses = m.cube(26).addresses().collect::<Vec<_>>();
+        assert_eq!(addresses, vec![16, 17, 18, 19, 24, 25, 26, 27]);
+    }
+
+    fn program(s: &str) -> Vec<Instruction> {
+        s.lines().map(|l| l.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_memory() {
+        let p = program(
+            "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X\nmem[8] = 11\nmem[7] = 101\nmem[8] = 0",
+        );
+        let m = Memory::run(&p, Decoder::V1);
+        assert_eq!(m.sum(), 165);
+        assert_eq!(
+            m.cells[&8],
+            Cell {
+                value: 64,
+                writer: 3
+            }
+        );
+        assert_eq!(m.mask_addresses, vec![(0, 2)]);
+
+        let mut out = vec![];
+        m.write_text(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "mem[7] = 101 (instruction 2)\nmem[8] = 64 (instruction 3)\n\
+             mask (instruction 0) touched 2 addresses\n"
+        );
+        let mut out = vec![];
+        m.write_json(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"{"memory":[{"address":7,"value":101,"writer":2},{"address":8,"value":64,"writer":3}],"masks":[{"instruction":0,"addresses":2}]}"#
+                .to_string()
+                + "\n"
+        );
+    }
+
+    #[test]
+    fn test_memory_diff() {
+        let p = program(
+            "mask = 000000000000000000000000000000X1001X\nmem[42] = 100\n\
+             mask = 00000000000000000000000000000000X0XX\nmem[26] = 1",
+        );
+        let v1 = Memory::run(&p, Decoder::V1);
+        let v2 = Memory::run(&p, Decoder::V2);
+        assert_eq!(v2.sum(), 208);
+        assert_eq!(v2.mask_addresses, vec![(0, 4), (2, 8)]);
+        assert_eq!(
+            v2.cells[&59],
+            Cell {
+                value: 100,
+                writer: 1
+            }
+        );
+
+        let diff = v1.diff(&v2);
+        assert_eq!(diff[0], (16, None, Some(1)));
+        assert!(diff.contains(&(42, Some(50), None)));
+        assert!(diff.iter().all(|d| d.0 != 26));
+        assert!(v2.diff(&v2).is_empty());
+    }
+
+    #[test]
+    fn test_execute_program_v2() {
+        let p = program(
+            "mask = 000000000000000000000000000000X1001X\nmem[42] = 100\n\
+             mask = 00000000000000000000000000000000X0XX\nmem[26] = 1",
+        );
+        assert_eq!(execute_program_v2(&p), 208);
+
+        // 2^34 addresses each, the second write overwrites a quarter of the first
+        let p = program(
+            "mask = 00XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX\nmem[0] = 3\n\
+             mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX00\nmem[0] = 5",
+        );
+        let quarter = 1u128 << 32;
+        assert_eq!(execute_program_v2(&p), 3 * 3 * quarter + 5 * 4 * quarter);
     }
 }
@@ -1,6 +1,6 @@
-use crate::grid::Grid;
+use crate::grid::{grid_cell, BoundingBox, Grid};
 use crate::solver::Solver;
-use std::convert::TryFrom;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::io::Read;
@@ -20,8 +20,10 @@ impl Solver for Problem {
         let mut gen = part1::grid_to_set(input);
 
         for _ in 0..6 {
+            self.show_step(&part1::slices(&gen));
             gen = part1::next_gen(&gen);
         }
+        self.show_step(&part1::slices(&gen));
 
         gen.len()
     }
@@ -30,229 +32,148 @@ impl Solver for Problem {
         let mut gen = part2::grid_to_set(input);
 
         for _ in 0..6 {
+            self.show_step(&part2::slices(&gen));
             gen = part2::next_gen(&gen);
         }
+        self.show_step(&part2::slices(&gen));
 
         gen.len()
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub enum Cube {
-    Inactive,
-    Active,
-}
-
-impl TryFrom<u8> for Cube {
-    type Error = String;
-
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            b'.' => Ok(Self::Inactive),
-            b'#' => Ok(Self::Active),
-            v => Err(format!("Invalid cell: {}", v)),
-        }
+grid_cell! {
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub enum Cube {
+        Inactive = '.',
+        Active = '#',
     }
 }
 
-impl Default for Cube {
-    fn default() -> Self {
-        Self::Inactive
-    }
-}
+/// Active cubes grouped by 2D slice, each slice labelled with its remaining coordinates.
+pub struct Slices(BTreeMap<String, HashSet<[isize; 2]>>);
 
-impl Display for Cube {
+impl Display for Slices {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::Inactive => '.',
-                Self::Active => '#',
-            }
-        )
+        let bounds = match BoundingBox::from_points(self.0.values().flatten().copied()) {
+            Some(b) => b,
+            None => return Ok(()),
+        };
+
+        for (label, slice) in &self.0 {
+            writeln!(f, "{}", label)?;
+            for y in bounds.min[1]..=bounds.max[1] {
+                for x in bounds.min[0]..=bounds.max[0] {
+                    let cube = if slice.contains(&[x, y]) {
+                        Cube::Active
+                    } else {
+                        Cube::Inactive
+                    };
+                    write!(f, "{}", cube)?;
+                }
+                writeln!(f)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
     }
 }
 
 mod part1 {
-    use crate::grid::Grid;
-    use crate::solutions::day17::Cube;
-    use itertools::Itertools;
-    use std::collections::HashSet;
+    use crate::grid::{BoundingBox, Grid};
+    use crate::solutions::day17::{Cube, Slices};
+    use std::collections::{BTreeMap, HashSet};
 
-    type Point = (isize, isize, isize);
+    type Point = [isize; 3];
 
     pub fn grid_to_set(cubes: &Grid<Cube>) -> HashSet<Point> {
-        (0..cubes.w)
-            .cartesian_product(0..cubes.h)
-            .filter(|&(x, y)| cubes.get((x, y)) == Some(&Cube::Active))
-            .map(|(x, y)| (x as isize, y as isize, 0))
+        cubes
+            .positions(|c| c == &Cube::Active)
+            .map(|pt| [pt.x as isize, pt.y as isize, 0])
             .collect()
     }
 
     pub fn next_gen(cubes: &HashSet<Point>) -> HashSet<Point> {
-        let mut next = HashSet::new();
-        let ((minx, miny, minz), (maxx, maxy, maxz)) = min_max_pt(cubes);
-
-        let coords = (minx - 1..=maxx + 1)
-            .cartesian_product(miny - 1..=maxy + 1)
-            .cartesian_product(minz - 1..=maxz + 1);
-
-        for ((x, y), z) in coords {
-            let pt = (x, y, z);
-            let neighbours = neighbours(&pt);
-            let neighbours_alive = n_alive(cubes, &neighbours);
-            let alive = cubes.contains(&pt);
-            let insert = match (alive, neighbours_alive) {
-                (true, 2) | (true, 3) => true,
-                (false, 3) => true,
-                _ => false,
-            };
-            if insert {
-                next.insert(pt);
-            }
-        }
-
-        next
+        let bounds = match BoundingBox::from_points(cubes.iter().copied()) {
+            Some(b) => b,
+            None => return HashSet::new(),
+        };
+
+        bounds
+            .expand(1)
+            .points()
+            .filter(|pt| {
+                let alive = cubes.contains(pt);
+                matches!((alive, n_alive(cubes, pt)), (true, 2) | (_, 3))
+            })
+            .collect()
     }
 
-    fn min_max_pt(cubes: &HashSet<Point>) -> (Point, Point) {
-        let (mut minx, mut miny, mut minz) = (0, 0, 0);
-        let (mut maxx, mut maxy, mut maxz) = (0, 0, 0);
-
-        for &(x, y, z) in cubes {
-            if x < minx {
-                minx = x;
-            }
-            if x > maxx {
-                maxx = x;
-            }
-
-            if y < miny {
-                miny = y;
-            }
-            if y > maxy {
-                maxy = y;
-            }
-
-            if z < minz {
-                minz = z;
-            }
-            if z > maxz {
-                maxz = z;
-            }
+    pub fn slices(cubes: &HashSet<Point>) -> Slices {
+        let mut slices = BTreeMap::new();
+        for &[x, y, z] in cubes {
+            slices
+                .entry(format!("z={}", z))
+                .or_insert_with(HashSet::new)
+                .insert([x, y]);
         }
-
-        ((minx, miny, minz), (maxx, maxy, maxz))
+        Slices(slices)
     }
 
-    fn neighbours(pt: &Point) -> Vec<Point> {
-        let (x, y, z) = pt;
-        (-1..=1)
-            .cartesian_product(-1..=1)
-            .cartesian_product(-1..=1)
-            .map(|((xn, yn), zn)| (xn + x, yn + y, zn + z))
-            .filter(|p| p != pt)
-            .collect()
-    }
-
-    fn n_alive(cubes: &HashSet<Point>, points: &[Point]) -> usize {
-        points.iter().filter(|&c| cubes.contains(c)).count()
+    fn n_alive(cubes: &HashSet<Point>, pt: &Point) -> usize {
+        BoundingBox::from_point(*pt)
+            .expand(1)
+            .points()
+            .filter(|p| p != pt && cubes.contains(p))
+            .count()
     }
 }
 
 mod part2 {
-    use crate::grid::Grid;
-    use crate::solutions::day17::Cube;
-    use itertools::Itertools;
-    use std::collections::HashSet;
+    use crate::grid::{BoundingBox, Grid};
+    use crate::solutions::day17::{Cube, Slices};
+    use std::collections::{BTreeMap, HashSet};
 
-    type Point = (isize, isize, isize, isize);
+    type Point = [isize; 4];
 
     pub fn grid_to_set(cubes: &Grid<Cube>) -> HashSet<Point> {
-        (0..cubes.w)
-            .cartesian_product(0..cubes.h)
-            .filter(|&(x, y)| cubes.get((x, y)) == Some(&Cube::Active))
-            .map(|(x, y)| (x as isize, y as isize, 0, 0))
+        cubes
+            .positions(|c| c == &Cube::Active)
+            .map(|pt| [pt.x as isize, pt.y as isize, 0, 0])
             .collect()
     }
 
     pub fn next_gen(cubes: &HashSet<Point>) -> HashSet<Point> {
-        let mut next = HashSet::new();
-        let ((minx, miny, minz, minw), (maxx, maxy, maxz, maxw)) = min_max_pt(cubes);
-
-        let coords = (minx - 1..=maxx + 1)
-            .cartesian_product(miny - 1..=maxy + 1)
-            .cartesian_product(minz - 1..=maxz + 1)
-            .cartesian_product(minw - 1..=maxw + 1);
-
-        for (((x, y), z), w) in coords {
-            let pt = (x, y, z, w);
-            let neighbours = neighbours(&pt);
-            let neighbours_alive = n_alive(cubes, &neighbours);
-            let alive = cubes.contains(&pt);
-            let insert = match (alive, neighbours_alive) {
-                (true, 2) | (true, 3) => true,
-                (false, 3) => true,
-                _ => false,
-            };
-            if insert {
-                next.insert(pt);
-            }
-        }
-
-        next
+        let bounds = match BoundingBox::from_points(cubes.iter().copied()) {
+            Some(b) => b,
+            None => return HashSet::new(),
+        };
+
+        bounds
+            .expand(1)
+            .points()
+            .filter(|pt| {
+                let alive = cubes.contains(pt);
+                matches!((alive, n_alive(cubes, pt)), (true, 2) | (_, 3))
+            })
+            .collect()
     }
 
-    fn min_max_pt(cubes: &HashSet<Point>) -> (Point, Point) {
-        let (mut minx, mut miny, mut minz, mut minw) = (0, 0, 0, 0);
-        let (mut maxx, mut maxy, mut maxz, mut maxw) = (0, 0, 0, 0);
-
-        for &(x, y, z, w) in cubes {
-            if x < minx {
-                minx = x;
-            }
-            if x > maxx {
-                maxx = x;
-            }
-
-            if y < miny {
-                miny = y;
-            }
-            if y > maxy {
-                maxy = y;
-            }
-
-            if z < minz {
-                minz = z;
-            }
-            if z > maxz {
-                maxz = z;
-            }
-
-            if w < minw {
-                minw = w;
-            }
-            if w > maxw {
-                maxw = w;
-            }
+    pub fn slices(cubes: &HashSet<Point>) -> Slices {
+        let mut slices = BTreeMap::new();
+        for &[x, y, z, w] in cubes {
+            slices
+                .entry(format!("z={}, w={}", z, w))
+                .or_insert_with(HashSet::new)
+                .insert([x, y]);
         }
-
-        ((minx, miny, minz, minw), (maxx, maxy, maxz, maxw))
-    }
-
-    fn neighbours(pt: &Point) -> Vec<Point> {
-        let (x, y, z, w) = pt;
-        (-1..=1)
-            .cartesian_product(-1..=1)
-            .cartesian_product(-1..=1)
-            .cartesian_product(-1..=1)
-            .map(|(((xn, yn), zn), wn)| (xn + x, yn + y, zn + z, wn + w))
-            .filter(|p| p != pt)
-            .collect()
+        Slices(slices)
     }
 
-    fn n_alive(cubes: &HashSet<Point>, points: &[Point]) -> usize {
-        points.iter().filter(|&c| cubes.contains(c)).count()
+    fn n_alive(cubes: &HashSet<Point>, pt: &Point) -> usize {
+        BoundingBox::from_point(*pt)
+            .expand(1)
+            .points()
+            .filter(|p| p != pt && cubes.contains(p))
+            .count()
     }
 }
@@ -1,3 +1,4 @@
+use crate::expr::{add, mul, Assoc, OperatorTable};
 use crate::solver::{ReadExt, Solver};
 use std::io::Read;
 
@@ -5,147 +6,87 @@ pub struct Problem;
 
 impl Solver for Problem {
     type Input = Vec<String>;
-    type Output1 = u64;
-    type Output2 = u64;
+    type Output1 = i128;
+    type Output2 = i128;
 
     fn parse_input<R: Read>(&self, r: R) -> Self::Input {
         r.split_lines()
     }
 
     fn solve_first(&self, input: &Self::Input) -> Self::Output1 {
-        input.iter().flat_map(|s| parser1::eval_expr(s)).sum()
+        sum(&same_precedence(), input)
     }
 
     fn solve_second(&self, input: &Self::Input) -> Self::Output2 {
-        input.iter().flat_map(|s| parser2::eval_expr(s)).sum()
+        sum(&addition_first(), input)
     }
 }
 
-mod parser1 {
-    use nom::branch::alt;
-    use nom::bytes::complete::tag;
-    use nom::character::complete::{char, digit1, multispace0};
-    use nom::combinator::{all_consuming, map_res};
-    use nom::multi::fold_many0;
-    use nom::sequence::{delimited, pair, preceded, terminated};
-    use nom::IResult;
-    use std::str::FromStr;
-
-    fn ws(i: &str) -> IResult<&str, &str> {
-        multispace0(i)
-    }
-
-    fn parens_expr(i: &str) -> IResult<&str, u64> {
-        let lparens = preceded(ws, tag("("));
-        let rparens = preceded(ws, tag(")"));
-        delimited(lparens, expr, rparens)(i)
-    }
-
-    fn term(i: &str) -> IResult<&str, u64> {
-        let number = map_res(preceded(ws, digit1), FromStr::from_str);
-        alt((number, parens_expr))(i)
-    }
-
-    fn expr(i: &str) -> IResult<&str, u64> {
-        let (i, first) = term(i)?;
-
-        let add = preceded(ws, char('+'));
-        let mul = preceded(ws, char('*'));
-        let op = alt((mul, add));
-        fold_many0(pair(op, term), first, |acc, (op, val)| {
-            if op == '+' {
-                acc + val
-            } else {
-                acc * val
+/// Sums the values of the expressions, reporting and skipping the invalid ones. Values are
+/// `i64`, so their sum can't overflow an `i128`.
+fn sum(rules: &OperatorTable, input: &[String]) -> i128 {
+    input
+        .iter()
+        .filter_map(|s| match rules.eval(s) {
+            Ok(v) => Some(i128::from(v)),
+            Err(e) => {
+                eprintln!("{:?}: {}", s, e);
+                None
             }
-        })(i)
-    }
-
-    fn complete_expr(s: &str) -> IResult<&str, u64> {
-        all_consuming(terminated(expr, ws))(s)
-    }
-
-    pub fn eval_expr(s: &str) -> Option<u64> {
-        complete_expr(s).ok().map(|(_, o)| o)
-    }
-
-    #[cfg(test)]
-    mod tests {
-        use super::*;
-
-        #[test]
-        fn test_parse() {
-            assert_eq!(complete_expr("5"), Ok(("", 5)));
-            assert_eq!(complete_expr("1 + 1"), Ok(("", 2)));
-            assert_eq!(complete_expr("(1 + 1)"), Ok(("", 2)));
-            assert_eq!(complete_expr("1 + 1 * 2"), Ok(("", 4)));
-            assert_eq!(complete_expr("1 + (1 * 2)"), Ok(("", 3)));
-        }
-    }
+        })
+        .sum()
 }
 
-mod parser2 {
-    use nom::branch::alt;
-    use nom::bytes::complete::tag;
-    use nom::character::complete::{char, digit1, multispace0};
-    use nom::combinator::{all_consuming, map_res};
-    use nom::multi::fold_many0;
-    use nom::sequence::{delimited, pair, preceded, terminated};
-    use nom::IResult;
-    use std::str::FromStr;
-
-    fn ws(i: &str) -> IResult<&str, &str> {
-        multispace0(i)
-    }
-
-    fn parens_expr(i: &str) -> IResult<&str, u64> {
-        let lparens = preceded(ws, tag("("));
-        let rparens = preceded(ws, tag(")"));
-        delimited(lparens, expr, rparens)(i)
-    }
-
-    fn term(i: &str) -> IResult<&str, u64> {
-        let number = map_res(preceded(ws, digit1), FromStr::from_str);
-
-        alt((number, parens_expr))(i)
-    }
-
-    fn factor(i: &str) -> IResult<&str, u64> {
-        let (i, first) = term(i)?;
-
-        let add = preceded(ws, char('+'));
-
-        fold_many0(pair(add, term), first, |acc, (_, val)| acc + val)(i)
-    }
-
-    fn expr(i: &str) -> IResult<&str, u64> {
-        let (i, first) = factor(i)?;
-
-        let mul = preceded(ws, char('*'));
-
-        fold_many0(pair(mul, factor), first, |acc, (_, val)| acc * val)(i)
-    }
-
-    fn complete_expr(s: &str) -> IResult<&str, u64> {
-        all_consuming(terminated(expr, ws))(s)
-    }
-
-    pub fn eval_expr(s: &str) -> Option<u64> {
-        complete_expr(s).ok().map(|(_, o)| o)
-    }
+/// Additions and multiplications are evaluated left to right.
+fn same_precedence() -> OperatorTable {
+    OperatorTable::new()
+        .binary("+", 1, Assoc::Left, add)
+        .binary("*", 1, Assoc::Left, mul)
+}
 
-    #[cfg(test)]
-    mod tests {
-        use super::*;
+/// Additions are evaluated before multiplications.
+fn addition_first() -> OperatorTable {
+    OperatorTable::new()
+        .binary("+", 2, Assoc::Left, add)
+        .binary("*", 1, Assoc::Left, mul)
+}
 
-        #[test]
-        fn test_parse() {
-            assert_eq!(complete_expr("5"), Ok(("", 5)));
-            assert_eq!(complete_expr("1 + 1"), Ok(("", 2)));
-            assert_eq!(complete_expr("(1 + 1)"), Ok(("", 2)));
-            assert_eq!(complete_expr("1 + 1 * 2"), Ok(("", 4)));
-            assert_eq!(complete_expr("1 + (1 * 2)"), Ok(("", 3)));
-            assert_eq!(complete_expr("2 * 3 + 1"), Ok(("", 8)));
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sum() {
+        let input = vec![
+            "9223372036854775807".to_string(),
+            "9223372036854775807 + 1".to_string(),
+            "1 + 9223372036854775806".to_string(),
+        ];
+        assert_eq!(sum(&same_precedence(), &input), 2 * i128::from(i64::MAX));
+    }
+
+    #[test]
+    fn test_same_precedence() {
+        let rules = same_precedence();
+        assert_eq!(rules.eval("5"), Ok(5));
+        assert_eq!(rules.eval("1 + 1"), Ok(2));
+        assert_eq!(rules.eval("(1 + 1)"), Ok(2));
+        assert_eq!(rules.eval("1 + 1 * 2"), Ok(4));
+        assert_eq!(rules.eval("1 + (1 * 2)"), Ok(3));
+    }
+
+    #[test]
+    fn test_addition_first() {
+        let rules = addition_first();
+        assert_eq!(rules.eval("5"), Ok(5));
+        assert_eq!(rules.eval("1 + 1"), Ok(2));
+        assert_eq!(rules.eval("(1 + 1)"), Ok(2));
+        assert_eq!(rules.eval("1 + 1 * 2"), Ok(4));
+        assert_eq!(rules.eval("1 + (1 * 2)"), Ok(3));
+        assert_eq!(rules.eval("2 * 3 + 1"), Ok(8));
+        assert_eq!(
+            rules.parse("1 + 2 * 3 + 4").unwrap().to_string(),
+            "((1 + 2) * (3 + 4))"
+        );
     }
 }
@@ -1,14 +1,31 @@
+use crate::visualize;
+use lazy_static::lazy_static;
 use std::fmt::Display;
 use std::fs::File;
 use std::io;
 use std::io::{BufRead, BufReader, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Mutex;
 
-fn input_file(day: i32) -> String {
+pub fn input_file(day: i32) -> String {
     format!("input/day{:02}", day)
 }
 
+lazy_static! {
+    static ref OUTPUT_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Makes solutions write the traces, dumps and other files they can produce to `dir`.
+pub fn enable_output(dir: &Path) {
+    *OUTPUT_DIR.lock().unwrap() = Some(dir.to_path_buf());
+}
+
+/// Where solutions write their files, unless it wasn't enabled.
+pub fn output_dir() -> Option<PathBuf> {
+    OUTPUT_DIR.lock().unwrap().clone()
+}
+
 pub trait Solver {
     type Input;
     type Output1: Display;
@@ -33,6 +50,12 @@ pub trait Solver {
         println!("Solution 1: {}", s1);
         println!("Solution 2: {}", s2);
     }
+
+    /// Called by solvers on each step of a simulation, to redraw `state` in the terminal when
+    /// running with `--visualize`.
+    fn show_step(&self, state: &impl Display) {
+        visualize::frame(state);
+    }
 }
 
 pub trait ReadExt<T> {
@@ -0,0 +1,38 @@
+use std::{
+    fmt::Display,
+    io::{stdout, Write},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    thread::sleep,
+    time::Duration,
+};
+
+pub const DEFAULT_FPS: u32 = 10;
+
+/// Delay between frames in microseconds, 0 when visualization is off.
+static FRAME_DELAY: AtomicU64 = AtomicU64::new(0);
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable(fps: u32) {
+    // at least 1µs, since 0 means off
+    let delay = (1_000_000 / u64::from(fps.max(1))).max(1);
+    FRAME_DELAY.store(delay, Ordering::Relaxed);
+}
+
+/// Redraws `state` in place of the previous frame, then waits for the next one. Does nothing
+/// unless visualization was enabled.
+pub fn frame(state: &impl Display) {
+    let delay = FRAME_DELAY.load(Ordering::Relaxed);
+    if delay == 0 {
+        return;
+    }
+
+    let mut out = stdout();
+    if !STARTED.swap(true, Ordering::Relaxed) {
+        // clear the screen once, then only move the cursor back home between frames
+        let _ = write!(out, "\x1b[2J");
+    }
+    let _ = write!(out, "\x1b[H{}\x1b[J", state);
+    let _ = out.flush();
+
+    sleep(Duration::from_micros(delay));
+}